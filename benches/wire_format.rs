@@ -0,0 +1,76 @@
+//! Compara el formato de texto con el binario al mandar cadenas completas y bloques sueltos.
+//! Se corre con `cargo bench`.
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use blockchain::blockchain::blockchain::{Blockchain, Transaction, TransactionData};
+use blockchain::communication::client_event::{ClientMessage, Message};
+use blockchain::communication::serialization::WireFormat;
+
+const ITERATIONS: u32 = 20;
+
+fn sample_chain(blocks: usize) -> Blockchain {
+    let mut blockchain = Blockchain::new();
+    for i in 0..blocks {
+        let transactions = (0..4)
+            .map(|j| {
+                let student = format!("Alumno {}", i * 4 + j);
+                Transaction::Insert(TransactionData::new(&student, "algo1", "parcial", 7))
+            })
+            .collect();
+        blockchain.add_transactions(transactions, 1, 1);
+    }
+    blockchain
+}
+
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn bench(name: &str, messages: &[Message]) {
+    println!("{}", name);
+    for format in [WireFormat::Text, WireFormat::Binary].iter() {
+        let mut bytes = Vec::new();
+        let encode = measure(|| {
+            bytes = messages.iter().flat_map(|m| format.encode(m)).collect();
+        });
+        let decode = measure(|| {
+            let decoded = format
+                .reader::<_, Message>(Cursor::new(bytes.clone()))
+                .filter(Result::is_ok)
+                .count();
+            assert_eq!(decoded, messages.len());
+        });
+        println!(
+            "  {:<6} {:>10} bytes  encode {:>10.3?}  decode {:>10.3?}",
+            format.name(),
+            bytes.len(),
+            encode,
+            decode
+        );
+    }
+}
+
+fn main() {
+    let blockchain = sample_chain(2000);
+    let response = Message::Common(ClientMessage::ReadBlockchainResponse {
+        request_id: 1,
+        blockchain: blockchain.clone(),
+    });
+    bench("blockchain response (2000 blocks)", &[response]);
+
+    let deltas: Vec<_> = blockchain
+        .blocks()
+        .iter()
+        .map(|block| {
+            Message::Common(ClientMessage::AppendBlock {
+                block: block.clone(),
+            })
+        })
+        .collect();
+    bench("append_block x 2000", &deltas);
+}
@@ -1,12 +1,25 @@
-use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fmt;
-use std::hash::Hasher;
-use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug)]
+use crate::blockchain::hash::{merkle_leaf, merkle_root, BlockHash, Sha256};
+use crate::blockchain::parse_error::{
+    next_field, parse_field, BlockParseError, BlockchainParseError, FieldError,
+    TransactionParseError,
+};
+use crate::blockchain::peer::PeerIdType;
+use crate::blockchain::state::{GradeChange, GradeState};
+use crate::communication::serialization::{put_u16, put_u32, put_u64, put_u8, ByteReader};
+use crate::communication::tokenizer::quote;
+
+const INSERT_TAG: u8 = 0;
+const REMOVE_TAG: u8 = 1;
+const UPDATE_TAG: u8 = 2;
+
+#[derive(Debug, PartialEq)]
 pub enum BlockError {
-    CreateError,
+    EmptyBlock,
+    InvalidTransaction { index: usize },
 }
 
 impl std::error::Error for BlockError {}
@@ -14,7 +27,162 @@ impl std::error::Error for BlockError {}
 impl fmt::Display for BlockError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            BlockError::CreateError => write!(f, "HTTP Error"),
+            BlockError::EmptyBlock => write!(f, "A block needs at least one transaction"),
+            BlockError::InvalidTransaction { index } => {
+                write!(f, "Transaction {} has invalid fields", index)
+            }
+        }
+    }
+}
+
+/// Primer problema encontrado al recorrer la cadena, con la altura del bloque afectado
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainError {
+    WrongHeight { height: usize },
+    InvalidTransaction { height: usize },
+    MerkleRootMismatch { height: usize },
+    HashMismatch { height: usize },
+    BrokenLink { height: usize },
+    UnknownTerm { height: usize, term: TermType },
+}
+
+impl std::error::Error for ChainError {}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::WrongHeight { height } => {
+                write!(f, "Block {} header has a different height", height)
+            }
+            ChainError::InvalidTransaction { height } => {
+                write!(f, "Block {} has an invalid transaction", height)
+            }
+            ChainError::MerkleRootMismatch { height } => {
+                write!(
+                    f,
+                    "Block {} merkle root does not match its transactions",
+                    height
+                )
+            }
+            ChainError::HashMismatch { height } => {
+                write!(f, "Block {} hash does not match its contents", height)
+            }
+            ChainError::BrokenLink { height } => {
+                write!(f, "Block {} does not link to the previous block", height)
+            }
+            ChainError::UnknownTerm { height, term } => {
+                write!(f, "Block {} claims term {}, never seen here", height, term)
+            }
+        }
+    }
+}
+
+/// Motivo por el que el líder rechaza una escritura antes de agregarla a la cadena
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    EmptyBlock,
+    InvalidTransaction { index: usize },
+    DuplicateGrade(GradeKey),
+    UnknownGrade(GradeKey),
+    StalePrevious { key: GradeKey, current: u16 },
+}
+
+impl std::error::Error for ValidationError {}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::EmptyBlock => write!(f, "The write has no transactions"),
+            ValidationError::InvalidTransaction { index } => {
+                write!(f, "Transaction {} has invalid fields", index)
+            }
+            ValidationError::DuplicateGrade(key) => {
+                write!(f, "Grade {} already exists, use update", key)
+            }
+            ValidationError::UnknownGrade(key) => write!(f, "Grade {} does not exist", key),
+            ValidationError::StalePrevious { key, current } => {
+                write!(f, "Grade {} is currently {}", key, current)
+            }
+        }
+    }
+}
+
+impl ValidationError {
+    pub fn serialize(&self) -> String {
+        match self {
+            ValidationError::EmptyBlock => "empty_block".to_owned(),
+            ValidationError::InvalidTransaction { index } => {
+                format!("invalid_transaction {}", index)
+            }
+            ValidationError::DuplicateGrade(key) => format!("duplicate_grade {}", key.serialize()),
+            ValidationError::UnknownGrade(key) => format!("unknown_grade {}", key.serialize()),
+            ValidationError::StalePrevious { key, current } => {
+                format!("stale_previous {} {}", key.serialize(), current)
+            }
+        }
+    }
+
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, FieldError> {
+        match next_field(tokens, "validation error")? {
+            "empty_block" => Ok(ValidationError::EmptyBlock),
+            "invalid_transaction" => {
+                let index = parse_field(tokens, "transaction index")?;
+                Ok(ValidationError::InvalidTransaction { index })
+            }
+            "duplicate_grade" => Ok(ValidationError::DuplicateGrade(GradeKey::parse(tokens)?)),
+            "unknown_grade" => Ok(ValidationError::UnknownGrade(GradeKey::parse(tokens)?)),
+            "stale_previous" => {
+                let key = GradeKey::parse(tokens)?;
+                let current = parse_field(tokens, "current score")?;
+                Ok(ValidationError::StalePrevious { key, current })
+            }
+            other => Err(FieldError::Invalid {
+                field: "validation error",
+                value: other.to_owned(),
+            }),
+        }
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ValidationError::EmptyBlock => put_u8(out, 0),
+            ValidationError::InvalidTransaction { index } => {
+                put_u8(out, 1);
+                put_u64(out, *index as u64);
+            }
+            ValidationError::DuplicateGrade(key) => {
+                put_u8(out, 2);
+                key.push_bytes(out);
+            }
+            ValidationError::UnknownGrade(key) => {
+                put_u8(out, 3);
+                key.push_bytes(out);
+            }
+            ValidationError::StalePrevious { key, current } => {
+                put_u8(out, 4);
+                key.push_bytes(out);
+                put_u16(out, *current);
+            }
+        }
+    }
+
+    pub fn decode(input: &mut ByteReader) -> Result<Self, FieldError> {
+        match input.u8("validation error")? {
+            0 => Ok(ValidationError::EmptyBlock),
+            1 => Ok(ValidationError::InvalidTransaction {
+                index: input.usize("transaction index")?,
+            }),
+            2 => Ok(ValidationError::DuplicateGrade(GradeKey::decode(input)?)),
+            3 => Ok(ValidationError::UnknownGrade(GradeKey::decode(input)?)),
+            4 => {
+                let key = GradeKey::decode(input)?;
+                let current = input.u16("current score")?;
+                Ok(ValidationError::StalePrevious { key, current })
+            }
+            other => Err(FieldError::Invalid {
+                field: "validation error",
+                value: other.to_string(),
+            }),
         }
     }
 }
@@ -22,146 +190,846 @@ impl fmt::Display for BlockError {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Transaction {
     Insert(TransactionData),
-    Remove(String),
+    Update {
+        data: TransactionData,
+        previous: u16,
+    },
+    Remove(GradeKey),
 }
 
 impl Transaction {
     pub fn is_valid(&self) -> bool {
         match self {
             Transaction::Insert(data) => data.is_valid(),
-            Transaction::Remove(key) => !key.is_empty(),
+            Transaction::Update { data, previous } => data.is_valid() && is_valid_score(*previous),
+            Transaction::Remove(key) => key.is_valid(),
+        }
+    }
+
+    pub fn key(&self) -> &GradeKey {
+        match self {
+            Transaction::Insert(data) | Transaction::Update { data, .. } => &data.key,
+            Transaction::Remove(key) => key,
         }
     }
 
     pub fn serialize(&self) -> String {
         match self {
             Transaction::Insert(data) => format!("insert {}", data.serialize()),
-            Transaction::Remove(key) => format!("remove {}", key),
+            Transaction::Update { data, previous } => format!(
+                "update {} {} {}",
+                data.key.serialize(),
+                previous,
+                data.score
+            ),
+            Transaction::Remove(key) => format!("remove {}", key.serialize()),
         }
     }
 
-    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Option<Self> {
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, TransactionParseError> {
         let action = tokens.next();
         match action {
-            Some("insert") => Some(Transaction::Insert(TransactionData::parse(tokens)?)),
-            Some("remove") => {
-                let name = tokens.next()?;
-                Some(Transaction::Remove(name.to_owned()))
+            Some("insert") => Ok(Transaction::Insert(TransactionData::parse(tokens)?)),
+            Some("update") => {
+                let key = GradeKey::parse(tokens)?;
+                let previous = parse_field(tokens, "previous score")?;
+                let score = parse_field(tokens, "score")?;
+                let data = TransactionData { key, score };
+                Ok(Transaction::Update { data, previous })
+            }
+            Some("remove") => Ok(Transaction::Remove(GradeKey::parse(tokens)?)),
+            Some(other) => Err(TransactionParseError::UnknownAction(other.to_owned())),
+            None => Err(TransactionParseError::MissingAction),
+        }
+    }
+
+    pub fn serialize_all(transactions: &[Transaction]) -> String {
+        transactions
+            .iter()
+            .map(Transaction::serialize)
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// La codificación binaria es la misma que se usa para las hojas del árbol de Merkle
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes());
+    }
+
+    pub fn decode(input: &mut ByteReader) -> Result<Self, TransactionParseError> {
+        if input.is_empty() {
+            return Err(TransactionParseError::MissingAction);
+        }
+        match input.u8("action")? {
+            INSERT_TAG => {
+                let key = GradeKey::decode(input)?;
+                let score = input.u16("score")?;
+                Ok(Transaction::Insert(TransactionData { key, score }))
+            }
+            UPDATE_TAG => {
+                let key = GradeKey::decode(input)?;
+                let previous = input.u16("previous score")?;
+                let score = input.u16("score")?;
+                let data = TransactionData { key, score };
+                Ok(Transaction::Update { data, previous })
+            }
+            REMOVE_TAG => Ok(Transaction::Remove(GradeKey::decode(input)?)),
+            other => Err(TransactionParseError::UnknownAction(other.to_string())),
+        }
+    }
+
+    pub fn encode_all(transactions: &[Transaction], out: &mut Vec<u8>) {
+        put_u32(out, transactions.len() as u32);
+        for transaction in transactions {
+            transaction.encode(out);
+        }
+    }
+
+    pub fn decode_all(input: &mut ByteReader) -> Result<Vec<Self>, TransactionParseError> {
+        let count = input.u32("transaction count")?;
+        let mut transactions = Vec::new();
+        for _ in 0..count {
+            transactions.push(Transaction::decode(input)?);
+        }
+        Ok(transactions)
+    }
+
+    /// Parsea transacciones hasta agotar los tokens; al menos una es obligatoria
+    pub fn parse_all(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<Vec<Self>, TransactionParseError> {
+        let mut tokens = tokens.peekable();
+        let mut transactions = vec![Transaction::parse(&mut tokens)?];
+        while tokens.peek().is_some() {
+            transactions.push(Transaction::parse(&mut tokens)?);
+        }
+        Ok(transactions)
+    }
+
+    /// Codificación canónica usada para el hash: tag del tipo, campos con largo prefijado
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Transaction::Insert(data) => {
+                bytes.push(INSERT_TAG);
+                data.key.push_bytes(&mut bytes);
+                bytes.extend_from_slice(&data.score.to_be_bytes());
+            }
+            Transaction::Update { data, previous } => {
+                bytes.push(UPDATE_TAG);
+                data.key.push_bytes(&mut bytes);
+                bytes.extend_from_slice(&previous.to_be_bytes());
+                bytes.extend_from_slice(&data.score.to_be_bytes());
+            }
+            Transaction::Remove(key) => {
+                bytes.push(REMOVE_TAG);
+                key.push_bytes(&mut bytes);
             }
-            _ => None,
         }
+        bytes
+    }
+}
+
+fn push_str(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn is_valid_score(score: u16) -> bool {
+    score > 0 && score <= 10
+}
+
+/// Identifica una nota: alumno, materia e instancia de evaluación (parcial, final, recuperatorio...)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GradeKey {
+    student: String,
+    course: String,
+    evaluation: String,
+}
+
+impl GradeKey {
+    pub fn new(student: &str, course: &str, evaluation: &str) -> Self {
+        Self {
+            student: student.to_owned(),
+            course: course.to_owned(),
+            evaluation: evaluation.to_owned(),
+        }
+    }
+
+    pub fn student(&self) -> &str {
+        &self.student
+    }
+
+    pub fn course(&self) -> &str {
+        &self.course
+    }
+
+    pub fn evaluation(&self) -> &str {
+        &self.evaluation
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.student.is_empty() && !self.course.is_empty() && !self.evaluation.is_empty()
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "{} {} {}",
+            quote(&self.student),
+            quote(&self.course),
+            quote(&self.evaluation)
+        )
+    }
+
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, FieldError> {
+        let student = next_field(tokens, "student")?;
+        let course = next_field(tokens, "course")?;
+        let evaluation = next_field(tokens, "evaluation")?;
+        Ok(Self::new(student, course, evaluation))
+    }
+
+    pub fn decode(input: &mut ByteReader) -> Result<Self, FieldError> {
+        let student = input.str("student")?;
+        let course = input.str("course")?;
+        let evaluation = input.str("evaluation")?;
+        Ok(Self::new(student, course, evaluation))
+    }
+
+    fn push_bytes(&self, bytes: &mut Vec<u8>) {
+        push_str(bytes, &self.student);
+        push_str(bytes, &self.course);
+        push_str(bytes, &self.evaluation);
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transaction::Insert(data) => write!(f, "insert {} -> {}", data.key, data.score),
+            Transaction::Update { data, previous } => {
+                write!(f, "update {} {} -> {}", data.key, previous, data.score)
+            }
+            Transaction::Remove(key) => write!(f, "remove {}", key),
+        }
+    }
+}
+
+impl fmt::Display for GradeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{} {}]", self.student, self.course, self.evaluation)
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TransactionData {
-    student: String,
+    key: GradeKey,
     score: u16,
 }
 
 impl TransactionData {
-    pub fn new(student: &str, score: u16) -> Self {
-        let student = student.to_owned();
-        Self { student, score }
+    pub fn new(student: &str, course: &str, evaluation: &str, score: u16) -> Self {
+        let key = GradeKey::new(student, course, evaluation);
+        Self { key, score }
+    }
+
+    pub fn key(&self) -> &GradeKey {
+        &self.key
+    }
+
+    pub fn score(&self) -> u16 {
+        self.score
     }
 
     pub fn serialize(&self) -> String {
-        format!("{} {}", self.student, self.score)
+        format!("{} {}", self.key.serialize(), self.score)
     }
 
-    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Option<Self> {
-        let student = tokens.next()?;
-        let score = tokens.next()?;
-        Some(Self::new(
-            student,
-            u16::from_str(score).expect("invalid score number"),
-        ))
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, FieldError> {
+        let key = GradeKey::parse(tokens)?;
+        let score = parse_field(tokens, "score")?;
+        Ok(Self { key, score })
     }
 
     pub fn is_valid(&self) -> bool {
-        self.score > 0 && self.score <= 10
+        self.key.is_valid() && is_valid_score(self.score)
+    }
+}
+
+/// Época de la elección del líder que commiteó un bloque
+pub type TermType = u64;
+
+/// Metadatos de un bloque. Todos sus campos están cubiertos por el hash del bloque.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    height: u64,
+    timestamp: u64,
+    author: PeerIdType,
+    term: TermType,
+    previous_hash: BlockHash,
+    merkle_root: BlockHash,
+}
+
+impl BlockHeader {
+    pub fn new(height: u64, previous_hash: BlockHash, author: PeerIdType, term: TermType) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        Self {
+            height,
+            timestamp,
+            author,
+            term,
+            previous_hash,
+            merkle_root: BlockHash::zero(),
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Milisegundos desde UNIX_EPOCH en que el bloque fue creado
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn author(&self) -> PeerIdType {
+        self.author
+    }
+
+    pub fn term(&self) -> TermType {
+        self.term
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.height, self.timestamp, self.author, self.term
+        )
+    }
+
+    fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, FieldError> {
+        let height = parse_field(tokens, "height")?;
+        let timestamp = parse_field(tokens, "timestamp")?;
+        let author = parse_field(tokens, "author")?;
+        let term = parse_field(tokens, "term")?;
+        Ok(Self {
+            height,
+            timestamp,
+            author,
+            term,
+            previous_hash: BlockHash::zero(),
+            merkle_root: BlockHash::zero(),
+        })
+    }
+
+    /// Codificación canónica usada para el hash del bloque
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        bytes.extend_from_slice(&self.author.to_be_bytes());
+        bytes.extend_from_slice(&self.term.to_be_bytes());
+        bytes.extend_from_slice(self.previous_hash.as_bytes());
+        bytes.extend_from_slice(self.merkle_root.as_bytes());
+        bytes
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Block {
-    transaction: Transaction,
-    previous_hash: u64,
-    hash: u64,
+    header: BlockHeader,
+    transactions: Vec<Transaction>,
+    hash: BlockHash,
 }
 
 impl Block {
-    pub fn new(transaction: Transaction, previous_hash: u64) -> Result<Self, BlockError> {
-        if !transaction.is_valid() {
-            return Err(BlockError::CreateError);
-        };
-        let hash = generate_hash(transaction.clone(), previous_hash);
+    pub fn new(
+        mut header: BlockHeader,
+        transactions: Vec<Transaction>,
+    ) -> Result<Self, BlockError> {
+        if transactions.is_empty() {
+            return Err(BlockError::EmptyBlock);
+        }
+        if let Some(index) = transactions.iter().position(|t| !t.is_valid()) {
+            return Err(BlockError::InvalidTransaction { index });
+        }
+        header.merkle_root = compute_merkle_root(&transactions);
+        let hash = Sha256::digest(&header.to_bytes());
         Ok(Self {
-            transaction,
-            previous_hash,
+            header,
+            transactions,
             hash,
         })
     }
 
     pub fn is_valid(&self) -> bool {
-        self.transaction.is_valid() && self.hash == hash_block(self.clone())
+        self.has_valid_transactions()
+            && self.header.merkle_root == compute_merkle_root(&self.transactions)
+            && self.hash == hash_block(self)
+    }
+
+    fn has_valid_transactions(&self) -> bool {
+        !self.transactions.is_empty() && self.transactions.iter().all(Transaction::is_valid)
+    }
+
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn merkle_root(&self) -> &BlockHash {
+        &self.header.merkle_root
+    }
+
+    pub fn hash(&self) -> &BlockHash {
+        &self.hash
+    }
+
+    pub fn previous_hash(&self) -> &BlockHash {
+        &self.header.previous_hash
     }
 
     pub fn serialize(&self) -> String {
         format!(
-            "{} {}",
-            self.transaction.serialize(),
-            format!("{}", self.previous_hash),
+            "block {} {} {} {} {}",
+            self.header.serialize(),
+            self.transactions.len(),
+            Transaction::serialize_all(&self.transactions),
+            self.header.previous_hash,
+            self.hash
         )
     }
+
+    /// Reconstruye un bloque recibido conservando su hash, sin recalcularlo.
+    /// La raíz de Merkle se deriva de las transacciones recibidas.
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, BlockParseError> {
+        let mut header = BlockHeader::parse(tokens)?;
+        let count: usize = parse_field(tokens, "transaction count")?;
+        let mut transactions = Vec::new();
+        for index in 0..count {
+            let transaction = Transaction::parse(tokens)
+                .map_err(|source| BlockParseError::Transaction { index, source })?;
+            transactions.push(transaction);
+        }
+        header.previous_hash = parse_hash(tokens, "previous hash")?;
+        header.merkle_root = compute_merkle_root(&transactions);
+        let hash = parse_hash(tokens, "hash")?;
+        Ok(Self {
+            header,
+            transactions,
+            hash,
+        })
+    }
+}
+
+impl Block {
+    /// Encabezado con su raíz de Merkle, transacciones y hash, sin recalcular nada:
+    /// `verify` detecta cualquier inconsistencia.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.header.to_bytes());
+        put_u32(out, self.transactions.len() as u32);
+        for transaction in &self.transactions {
+            transaction.encode(out);
+        }
+        out.extend_from_slice(self.hash.as_bytes());
+    }
+
+    pub fn decode(input: &mut ByteReader) -> Result<Self, BlockParseError> {
+        let header = BlockHeader {
+            height: input.u64("height")?,
+            timestamp: input.u64("timestamp")?,
+            author: input.u32("author")?,
+            term: input.u64("term")?,
+            previous_hash: input.hash("previous hash")?,
+            merkle_root: input.hash("merkle root")?,
+        };
+        let count = input.u32("transaction count")? as usize;
+        let mut transactions = Vec::new();
+        for index in 0..count {
+            let transaction = Transaction::decode(input)
+                .map_err(|source| BlockParseError::Transaction { index, source })?;
+            transactions.push(transaction);
+        }
+        let hash = input.hash("hash")?;
+        Ok(Self {
+            header,
+            transactions,
+            hash,
+        })
+    }
+}
+
+fn parse_hash(
+    tokens: &mut dyn Iterator<Item = &str>,
+    field: &'static str,
+) -> Result<BlockHash, FieldError> {
+    let value = next_field(tokens, field)?;
+    BlockHash::parse(value).ok_or_else(|| FieldError::Invalid {
+        field,
+        value: value.to_owned(),
+    })
+}
+
+fn hash_block(record: &Block) -> BlockHash {
+    Sha256::digest(&record.header.to_bytes())
+}
+
+fn compute_merkle_root(transactions: &[Transaction]) -> BlockHash {
+    let leaves: Vec<BlockHash> = transactions
+        .iter()
+        .map(|transaction| merkle_leaf(&transaction.to_bytes()))
+        .collect();
+    merkle_root(&leaves)
 }
 
-fn hash_block(record: Block) -> u64 {
-    generate_hash(record.transaction, record.previous_hash)
+/// Referencia a un bloque de la cadena por altura o por hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockRef {
+    Height(usize),
+    Hash(BlockHash),
 }
 
-fn generate_hash(transaction: Transaction, previous_hash: u64) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    match transaction {
-        Transaction::Insert(data) => {
-            hasher.write(data.student.as_bytes());
-            hasher.write_u16(data.score);
+impl BlockRef {
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, FieldError> {
+        let value = next_field(tokens, "block")?;
+        if let Some(hash) = BlockHash::parse(value) {
+            return Ok(BlockRef::Hash(hash));
         }
-        Transaction::Remove(key) => {
-            hasher.write(key.as_bytes());
+        value
+            .parse()
+            .map(BlockRef::Height)
+            .map_err(|_| FieldError::Invalid {
+                field: "block",
+                value: value.to_owned(),
+            })
+    }
+}
+
+impl fmt::Display for BlockRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockRef::Height(height) => write!(f, "{}", height),
+            BlockRef::Hash(hash) => write!(f, "{}", hash),
         }
-    };
-    hasher.write_u64(previous_hash);
-    hasher.finish()
+    }
 }
+
+/// Resultado de comparar la cadena local con una recibida de otro nodo
+#[derive(Debug, PartialEq)]
+pub enum Reconciliation {
+    /// La cadena recibida es igual a la local o un prefijo de ella
+    Stale,
+    /// La cadena recibida extiende a la local con `added` bloques nuevos
+    Extended { added: usize },
+    /// Las cadenas divergen después del bloque `ancestor` (`None` si ya difieren en el primero).
+    /// `discarded` son las transacciones de la rama perdedora.
+    Fork {
+        ancestor: Option<usize>,
+        adopted: bool,
+        discarded: Vec<Transaction>,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct Blockchain {
     blocks: Vec<Block>,
+    state: GradeState,
 }
 
 impl Blockchain {
     pub fn new() -> Self {
-        Self { blocks: Vec::new() }
+        Self {
+            blocks: Vec::new(),
+            state: GradeState::new(),
+        }
     }
 
     pub fn add_block(&mut self, block: Block) {
+        for transaction in block.transactions() {
+            self.state.apply(transaction);
+        }
         self.blocks.push(block)
     }
+
+    /// Estado vigente de las notas, mantenido a medida que se agregan bloques
+    pub fn state(&self) -> &GradeState {
+        &self.state
+    }
     pub fn get_last(&self) -> Option<&Block> {
         self.blocks.last()
     }
 
-    pub fn add_transaction(&mut self, transaction: Transaction) {
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn height(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    /// Altura del bloque referenciado, si está en la cadena
+    pub fn resolve(&self, block: &BlockRef) -> Option<usize> {
+        match block {
+            BlockRef::Height(height) if *height < self.blocks.len() => Some(*height),
+            BlockRef::Height(_) => None,
+            BlockRef::Hash(hash) => self.blocks.iter().position(|b| b.hash == *hash),
+        }
+    }
+
+    /// Estado de las notas inmediatamente después de aplicar el bloque `height`
+    pub fn state_at(&self, height: usize) -> Option<GradeState> {
+        let blocks = self.blocks.get(..=height)?;
+        let mut state = GradeState::new();
+        for transaction in blocks.iter().flat_map(Block::transactions) {
+            state.apply(transaction);
+        }
+        Some(state)
+    }
+
+    /// Todas las transacciones que tocaron notas del alumno, en orden de la cadena
+    pub fn history_of(&self, student: &str) -> Vec<GradeChange> {
+        self.changes(&self.blocks)
+            .filter(|change| change.transaction().key().student() == student)
+            .collect()
+    }
+
+    /// Transacciones de los bloques posteriores a `from` hasta `to` inclusive, es decir lo que
+    /// lleva de `state_at(from)` a `state_at(to)`
+    pub fn changes_between(&self, from: usize, to: usize) -> Option<Vec<GradeChange>> {
+        if from > to {
+            return None;
+        }
+        let blocks = self.blocks.get(from + 1..=to)?;
+        Some(self.changes(blocks).collect())
+    }
+
+    fn changes<'a>(&self, blocks: &'a [Block]) -> impl Iterator<Item = GradeChange> + 'a {
+        blocks.iter().flat_map(|block| {
+            block.transactions.iter().map(move |transaction| {
+                GradeChange::new(
+                    block.header.height as usize,
+                    block.header.timestamp,
+                    transaction.clone(),
+                )
+            })
+        })
+    }
+
+    pub fn add_transactions(
+        &mut self,
+        transactions: Vec<Transaction>,
+        author: PeerIdType,
+        term: TermType,
+    ) {
         let prev_hash = if let Some(last) = self.get_last() {
             last.hash
         } else {
-            0
+            BlockHash::zero()
+        };
+        let header = BlockHeader::new(self.height(), prev_hash, author, term);
+        let block = Block::new(header, transactions).unwrap();
+        self.add_block(block);
+    }
+    /// Valida una escritura contra el estado vigente. Las transacciones se aplican en orden,
+    /// así que una puede depender de otra anterior del mismo bloque.
+    pub fn validate(&self, transactions: &[Transaction]) -> Result<(), ValidationError> {
+        if transactions.is_empty() {
+            return Err(ValidationError::EmptyBlock);
+        }
+        let mut pending: HashMap<&GradeKey, Option<u16>> = HashMap::new();
+        for (index, transaction) in transactions.iter().enumerate() {
+            if !transaction.is_valid() {
+                return Err(ValidationError::InvalidTransaction { index });
+            }
+            let key = transaction.key();
+            let current = match pending.get(key) {
+                Some(score) => *score,
+                None => self.state.get(key),
+            };
+            match (transaction, current) {
+                (Transaction::Insert(_), Some(_)) => {
+                    return Err(ValidationError::DuplicateGrade(key.clone()))
+                }
+                (Transaction::Update { .. }, None) | (Transaction::Remove(_), None) => {
+                    return Err(ValidationError::UnknownGrade(key.clone()))
+                }
+                (Transaction::Update { previous, .. }, Some(current)) if *previous != current => {
+                    return Err(ValidationError::StalePrevious {
+                        key: key.clone(),
+                        current,
+                    })
+                }
+                (Transaction::Insert(data), None) | (Transaction::Update { data, .. }, Some(_)) => {
+                    pending.insert(key, Some(data.score()));
+                }
+                (Transaction::Remove(_), Some(_)) => {
+                    pending.insert(key, None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Altura del último bloque que comparten ambas cadenas. Como cada bloque incluye el hash
+    /// del anterior, las cadenas coinciden hasta el primer hash distinto.
+    pub fn common_ancestor(&self, other: &Blockchain) -> Option<usize> {
+        self.blocks
+            .iter()
+            .zip(other.blocks.iter())
+            .take_while(|(ours, theirs)| ours.hash == theirs.hash)
+            .count()
+            .checked_sub(1)
+    }
+
+    /// Incorpora una cadena recibida. Las extensiones estrictas se aceptan; ante un fork gana
+    /// la rama cuyo último bloque tiene el término de líder más alto y, a igual término, la
+    /// más larga. Si empatan se conserva la local. Una rama que termina en un término mayor a
+    /// `seen_term`, el más alto que vio este nodo, se rechaza: el término lo declara el bloque
+    /// y sin ese tope un nodo ganaría cualquier fork con sólo inventarse uno alto.
+    pub fn reconcile(
+        &mut self,
+        incoming: Blockchain,
+        seen_term: TermType,
+    ) -> Result<Reconciliation, ChainError> {
+        self.reconcile_segment(0, incoming.blocks, seen_term)
+    }
+
+    /// Como `reconcile`, pero con los bloques `from..` de la otra cadena, que deben
+    /// encadenarse con el bloque `from - 1` local. Permite sincronizar sin recibir la cadena
+    /// completa.
+    pub fn reconcile_segment(
+        &mut self,
+        from: usize,
+        blocks: Vec<Block>,
+        seen_term: TermType,
+    ) -> Result<Reconciliation, ChainError> {
+        let previous = self
+            .previous_of(from)
+            .ok_or(ChainError::BrokenLink { height: from })?;
+        Blockchain::verify_segment(from, &previous, &blocks)?;
+        let matching = self.matching(from, &blocks);
+        let shared = from + matching;
+        if matching == blocks.len() {
+            return Ok(Reconciliation::Stale);
+        }
+        let branch = blocks.into_iter().skip(matching);
+        if shared == self.blocks.len() {
+            let before = self.blocks.len();
+            branch.for_each(|block| self.add_block(block));
+            let added = self.blocks.len() - before;
+            return Ok(Reconciliation::Extended { added });
+        }
+        let branch: Vec<Block> = branch.collect();
+        let theirs = branch.last().map_or(0, |block| block.header.term);
+        if theirs > seen_term {
+            return Err(ChainError::UnknownTerm {
+                height: shared + branch.len() - 1,
+                term: theirs,
+            });
+        }
+        let adopted = (theirs, shared + branch.len()) > self.fork_rank();
+        let discarded = if adopted {
+            let discarded = self.truncate(shared);
+            branch.into_iter().for_each(|block| self.add_block(block));
+            discarded
+        } else {
+            branch
+                .iter()
+                .flat_map(|block| block.transactions.clone())
+                .collect()
+        };
+        Ok(Reconciliation::Fork {
+            ancestor: shared.checked_sub(1),
+            adopted,
+            discarded,
+        })
+    }
+
+    /// Hash con el que debe encadenarse un bloque de altura `height`
+    pub fn previous_of(&self, height: usize) -> Option<BlockHash> {
+        match height {
+            0 => Some(BlockHash::zero()),
+            _ => self.blocks.get(height - 1).map(|block| block.hash),
+        }
+    }
+
+    /// Cuántos de `blocks`, que empiezan en la altura `from`, coinciden con los locales
+    pub fn matching(&self, from: usize, blocks: &[Block]) -> usize {
+        self.blocks
+            .iter()
+            .skip(from)
+            .zip(blocks)
+            .take_while(|(ours, theirs)| ours.hash == theirs.hash)
+            .count()
+    }
+
+    /// Verifica bloques consecutivos que empiezan en `from` y se encadenan con `previous`
+    pub fn verify_segment(
+        from: usize,
+        previous: &BlockHash,
+        blocks: &[Block],
+    ) -> Result<(), ChainError> {
+        let mut expected_previous = *previous;
+        for (offset, block) in blocks.iter().enumerate() {
+            Blockchain::check_block(block, from + offset, &expected_previous)?;
+            expected_previous = block.hash;
+        }
+        Ok(())
+    }
+
+    /// Descarta los bloques desde `len` y devuelve sus transacciones
+    fn truncate(&mut self, len: usize) -> Vec<Transaction> {
+        let removed = self.blocks.split_off(len);
+        self.state = match len.checked_sub(1) {
+            Some(last) => self.state_at(last).unwrap_or_default(),
+            None => GradeState::new(),
         };
-        let block = Block::new(transaction, prev_hash).unwrap();
+        removed
+            .into_iter()
+            .flat_map(|block| block.transactions)
+            .collect()
+    }
+
+    fn fork_rank(&self) -> (TermType, usize) {
+        let term = self.get_last().map_or(0, |block| block.header.term);
+        (term, self.blocks.len())
+    }
+
+    /// Recorre la cadena desde el primer bloque y devuelve el primer error encontrado
+    pub fn verify(&self) -> Result<(), ChainError> {
+        Blockchain::verify_segment(0, &BlockHash::zero(), &self.blocks)
+    }
+
+    /// Agrega un bloque recibido sólo si es válido y se encadena con el último
+    pub fn append(&mut self, block: Block) -> Result<(), ChainError> {
+        let tip = self.get_last().map_or(BlockHash::zero(), |last| last.hash);
+        Blockchain::check_block(&block, self.blocks.len(), &tip)?;
         self.add_block(block);
+        Ok(())
     }
-    pub fn validate(&self, _transaction: &Transaction) -> bool {
-        true
+
+    fn check_block(block: &Block, height: usize, previous: &BlockHash) -> Result<(), ChainError> {
+        if block.header.height != height as u64 {
+            return Err(ChainError::WrongHeight { height });
+        }
+        if !block.has_valid_transactions() {
+            return Err(ChainError::InvalidTransaction { height });
+        }
+        if block.header.merkle_root != compute_merkle_root(&block.transactions) {
+            return Err(ChainError::MerkleRootMismatch { height });
+        }
+        if block.hash != hash_block(block) {
+            return Err(ChainError::HashMismatch { height });
+        }
+        if block.header.previous_hash != *previous {
+            return Err(ChainError::BrokenLink { height });
+        }
+        Ok(())
     }
 
     pub fn serialize(&self) -> String {
@@ -176,14 +1044,41 @@ impl Blockchain {
         format!("{} end_blockchain", response)
     }
 
-    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Option<Self> {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.height());
+        for block in &self.blocks {
+            block.encode(out);
+        }
+    }
+
+    pub fn decode(input: &mut ByteReader) -> Result<Self, BlockchainParseError> {
+        let count = input
+            .usize("block count")
+            .map_err(|_| BlockchainParseError::MissingEnd)?;
         let mut blockchain = Blockchain::new();
-        while let Some(transaction) = Transaction::parse(tokens) {
-            let previous_hash = tokens.next()?;
-            let block = Block::new(transaction, previous_hash.parse::<u64>().ok()?).ok()?;
+        for height in 0..count {
+            let block = Block::decode(input)
+                .map_err(|source| BlockchainParseError::Block { height, source })?;
             blockchain.add_block(block);
         }
-        Some(blockchain)
+        Ok(blockchain)
+    }
+
+    pub fn parse(tokens: &mut dyn Iterator<Item = &str>) -> Result<Self, BlockchainParseError> {
+        let mut blockchain = Blockchain::new();
+        loop {
+            match tokens.next() {
+                Some("block") => {
+                    let height = blockchain.blocks.len();
+                    let block = Block::parse(tokens)
+                        .map_err(|source| BlockchainParseError::Block { height, source })?;
+                    blockchain.add_block(block);
+                }
+                Some("end_blockchain") => return Ok(blockchain),
+                Some(other) => return Err(BlockchainParseError::UnexpectedToken(other.to_owned())),
+                None => return Err(BlockchainParseError::MissingEnd),
+            }
+        }
     }
 }
 
@@ -195,71 +1090,64 @@ impl Default for Blockchain {
 
 impl fmt::Display for Blockchain {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut entries = HashMap::new();
-        for block in &self.blocks {
-            match &block.transaction {
-                Transaction::Insert(data) => {
-                    let key = data.student.clone();
-                    entries.insert(key, data.score);
-                }
-                Transaction::Remove(student) => {
-                    entries.remove(student);
-                }
-            }
-        }
-        for (student, score) in entries.iter() {
-            writeln!(f, "Student {} -> {}", student, score)?;
-        }
-        Ok(())
+        write!(f, "{}", self.state)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::communication::tokenizer::tokenize;
+
+    fn header(previous_hash: BlockHash) -> BlockHeader {
+        let mut header = BlockHeader::new(0, previous_hash, 1, 1);
+        header.timestamp = 1_600_000_000_000;
+        header
+    }
 
     #[test]
     fn test_valid_transaction() {
-        let transaction_data = TransactionData::new("Pedro", 10);
-        assert_eq!(transaction_data.is_valid(), true)
+        let transaction_data = TransactionData::new("Pedro", "algo1", "parcial", 10);
+        assert!(transaction_data.is_valid())
     }
 
     #[test]
     fn test_invalid_transaction_greater_than_max() {
-        let transaction_data = TransactionData::new("Pedro", u16::MAX);
-        assert_eq!(transaction_data.is_valid(), false)
+        let transaction_data = TransactionData::new("Pedro", "algo1", "parcial", u16::MAX);
+        assert!(!transaction_data.is_valid())
     }
 
     #[test]
     fn first_block_must_be_valid() {
-        let transaction_data = TransactionData::new("Pedro", 7);
+        let transaction_data = TransactionData::new("Pedro", "algo1", "parcial", 7);
         let transaction = Transaction::Insert(transaction_data);
-        let block = Block::new(transaction, 0).unwrap();
-        assert_eq!(block.is_valid(), true);
+        let block = Block::new(header(BlockHash::zero()), vec![transaction]).unwrap();
+        assert!(block.is_valid());
     }
     #[test]
     fn block_with_invalid_transaction_should_be_not_created() {
-        let transaction_data = TransactionData::new("Pedro", u16::MAX);
+        let transaction_data = TransactionData::new("Pedro", "algo1", "parcial", u16::MAX);
         let transaction = Transaction::Insert(transaction_data);
-        let block = Block::new(transaction, 0);
+        let block = Block::new(header(BlockHash::zero()), vec![transaction]);
         assert!(block.is_err());
     }
 
     #[test]
     fn different_blocks_with_same_content_must_be_eql() {
-        let transaction_data = TransactionData::new("Pedro", 7);
+        let transaction_data = TransactionData::new("Pedro", "algo1", "parcial", 7);
         let transaction = Transaction::Insert(transaction_data);
-        let block = Block::new(transaction.clone(), 1245).unwrap();
-        let block2 = Block::new(transaction, 1245).unwrap();
+        let previous_hash = Sha256::digest(b"1245");
+        let block = Block::new(header(previous_hash), vec![transaction.clone()]).unwrap();
+        let block2 = Block::new(header(previous_hash), vec![transaction]).unwrap();
         assert_eq!(block.hash, block2.hash);
     }
 
     #[test]
     fn block_must_be_the_same_after_adding_to_bc() {
-        let transaction_data = TransactionData::new("Pedro", 7);
+        let transaction_data = TransactionData::new("Pedro", "algo1", "parcial", 7);
         let transaction = Transaction::Insert(transaction_data);
         let mut bc = Blockchain::new();
-        let block = Block::new(transaction, 0).unwrap();
+        let block = Block::new(header(BlockHash::zero()), vec![transaction]).unwrap();
         bc.add_block(block.clone());
         let last_block = bc.get_last().unwrap();
         assert_eq!(*last_block, block);
@@ -267,21 +1155,804 @@ mod tests {
 
     #[test]
     fn parse_transaction() {
-        let transaction_str = "insert pedro 10 0 insert juan 2 123";
+        let transaction_str = "insert pedro algo1 parcial 10 0 insert juan algo1 parcial 2 123";
         let mut tokens = transaction_str.split_whitespace();
         let transaction = Transaction::parse(&mut tokens);
         let _prev_hash = tokens.next();
         let transaction_2 = Transaction::parse(&mut tokens);
-        assert!(transaction.is_some());
-        assert!(transaction_2.is_some());
+        assert!(transaction.is_ok());
+        assert!(transaction_2.is_ok());
     }
 
     #[test]
     fn parse_blockchain() {
-        let blockchain_str =
-            "blockchain insert pedro 10 0 123 insert juan 2 123 345 end_blockchain";
+        let zero = BlockHash::zero();
+        let blockchain_str = format!(
+            "blockchain block 0 0 1 1 1 insert pedro algo1 parcial 10 {} {} block 1 0 1 1 1 insert juan algo1 parcial 2 {} {} end_blockchain",
+            zero, zero, zero, zero
+        );
         let mut tokens = blockchain_str.split_whitespace().skip(1);
         let blockchain = Blockchain::parse(&mut tokens);
-        assert!(blockchain.is_some());
+        assert!(blockchain.is_ok());
+    }
+
+    #[test]
+    fn block_hash_matches_known_vectors() {
+        let insert = Transaction::Insert(TransactionData::new("Pedro", "algo1", "parcial", 7));
+        let block = Block::new(header(BlockHash::zero()), vec![insert.clone()]).unwrap();
+        assert_eq!(
+            block.hash.to_string(),
+            "efdaa7d05846ab7d87a25df0a05261b2ff4a5396c673008810854fa2bba4c4bb"
+        );
+
+        let remove = Transaction::Remove(GradeKey::new("Juan", "algo1", "parcial"));
+        let block = Block::new(header(BlockHash::zero()), vec![insert, remove]).unwrap();
+        assert_eq!(
+            block.hash.to_string(),
+            "0510d1e78b59bd74980166f831b79a6dad95e0cbc579d756a31691be8789426e"
+        );
+    }
+
+    #[test]
+    fn empty_block_should_be_not_created() {
+        assert!(Block::new(header(BlockHash::zero()), vec![]).is_err());
+    }
+
+    #[test]
+    fn block_with_one_invalid_transaction_should_be_not_created() {
+        let valid = Transaction::Insert(TransactionData::new("Pedro", "algo1", "parcial", 7));
+        let invalid = Transaction::Insert(TransactionData::new("Juan", "algo1", "parcial", 11));
+        assert!(Block::new(header(BlockHash::zero()), vec![valid, invalid]).is_err());
+    }
+
+    #[test]
+    fn many_transactions_fit_in_one_block() {
+        let transactions: Vec<Transaction> = (0..200)
+            .map(|i| {
+                Transaction::Insert(TransactionData::new(
+                    &format!("student{}", i),
+                    "algo1",
+                    "parcial",
+                    7,
+                ))
+            })
+            .collect();
+        let mut bc = Blockchain::new();
+        bc.add_transactions(transactions.clone(), 1, 1);
+        assert_eq!(bc.blocks.len(), 1);
+        assert_eq!(bc.get_last().unwrap().transactions(), &transactions[..]);
+        let serialized = bc.serialize();
+        let parsed = Blockchain::parse(&mut serialized.split_whitespace()).unwrap();
+        assert_eq!(parsed.blocks, bc.blocks);
+        assert_eq!(parsed.verify(), Ok(()));
+    }
+
+    #[test]
+    fn reordered_transactions_change_the_merkle_root() {
+        let a = Transaction::Insert(TransactionData::new("a", "algo1", "parcial", 1));
+        let b = Transaction::Insert(TransactionData::new("b", "algo1", "parcial", 2));
+        let block = Block::new(header(BlockHash::zero()), vec![a.clone(), b.clone()]).unwrap();
+        let swapped = Block::new(header(BlockHash::zero()), vec![b, a]).unwrap();
+        assert_ne!(block.merkle_root(), swapped.merkle_root());
+        assert_ne!(block.hash(), swapped.hash());
+    }
+
+    #[test]
+    fn block_hash_depends_on_previous_hash() {
+        let transaction = Transaction::Insert(TransactionData::new("Pedro", "algo1", "parcial", 7));
+        let block = Block::new(header(BlockHash::zero()), vec![transaction.clone()]).unwrap();
+        let block2 = Block::new(header(block.hash), vec![transaction]).unwrap();
+        assert_ne!(block.hash, block2.hash);
+    }
+
+    #[test]
+    fn serialized_chain_round_trips() {
+        let mut bc = Blockchain::new();
+        bc.add_transactions(
+            vec![Transaction::Insert(TransactionData::new(
+                "pedro", "algo1", "parcial", 10,
+            ))],
+            1,
+            1,
+        );
+        bc.add_transactions(
+            vec![Transaction::Remove(GradeKey::new(
+                "pedro", "algo1", "parcial",
+            ))],
+            1,
+            1,
+        );
+        let serialized = bc.serialize();
+        let parsed = Blockchain::parse(&mut serialized.split_whitespace()).unwrap();
+        assert_eq!(parsed.blocks, bc.blocks);
+    }
+
+    fn sample_chain() -> Blockchain {
+        let mut bc = Blockchain::new();
+        bc.add_transactions(
+            vec![Transaction::Insert(TransactionData::new(
+                "pedro", "algo1", "parcial", 10,
+            ))],
+            1,
+            1,
+        );
+        bc.add_transactions(
+            vec![Transaction::Insert(TransactionData::new(
+                "juan", "algo1", "parcial", 2,
+            ))],
+            1,
+            1,
+        );
+        bc.add_transactions(
+            vec![Transaction::Remove(GradeKey::new(
+                "pedro", "algo1", "parcial",
+            ))],
+            1,
+            1,
+        );
+        bc
+    }
+
+    #[test]
+    fn built_chain_verifies() {
+        assert_eq!(sample_chain().verify(), Ok(()));
+        assert_eq!(Blockchain::new().verify(), Ok(()));
+    }
+
+    #[test]
+    fn tampered_transaction_is_reported_as_hash_mismatch() {
+        let mut bc = sample_chain();
+        bc.blocks[1] = Block::parse(
+            &mut bc.blocks[1]
+                .serialize()
+                .replacen("juan algo1 parcial 2", "juan algo1 parcial 9", 1)
+                .split_whitespace()
+                .skip(1),
+        )
+        .unwrap();
+        assert_eq!(bc.verify(), Err(ChainError::HashMismatch { height: 1 }));
+    }
+
+    #[test]
+    fn rehashed_tampered_block_breaks_the_next_link() {
+        let mut bc = sample_chain();
+        let previous_hash = bc.blocks[0].header.previous_hash;
+        let transaction = Transaction::Insert(TransactionData::new("pedro", "algo1", "parcial", 4));
+        bc.blocks[0] = Block::new(header(previous_hash), vec![transaction]).unwrap();
+        assert_eq!(bc.verify(), Err(ChainError::BrokenLink { height: 1 }));
+    }
+
+    #[test]
+    fn first_block_must_link_to_zero_hash() {
+        let mut bc = Blockchain::new();
+        let transaction = Transaction::Insert(TransactionData::new("pedro", "algo1", "parcial", 4));
+        bc.add_block(Block::new(header(Sha256::digest(b"other")), vec![transaction]).unwrap());
+        assert_eq!(bc.verify(), Err(ChainError::BrokenLink { height: 0 }));
+    }
+
+    #[test]
+    fn invalid_transaction_is_reported_first() {
+        let mut bc = sample_chain();
+        bc.blocks[2].transactions =
+            vec![Transaction::Remove(GradeKey::new("", "algo1", "parcial"))];
+        assert_eq!(
+            bc.verify(),
+            Err(ChainError::InvalidTransaction { height: 2 })
+        );
+    }
+
+    #[test]
+    fn parse_keeps_received_hashes() {
+        let bc = sample_chain();
+        let tampered = bc
+            .serialize()
+            .replacen("juan algo1 parcial 2", "juan algo1 parcial 9", 1);
+        let parsed = Blockchain::parse(&mut tampered.split_whitespace()).unwrap();
+        assert_eq!(parsed.verify(), Err(ChainError::HashMismatch { height: 1 }));
+    }
+
+    #[test]
+    fn header_fields_are_covered_by_the_hash() {
+        let transaction = Transaction::Insert(TransactionData::new("Pedro", "algo1", "parcial", 7));
+        let block = Block::new(header(BlockHash::zero()), vec![transaction.clone()]).unwrap();
+
+        let mut other = header(BlockHash::zero());
+        other.author = 2;
+        let by_other_author = Block::new(other, vec![transaction.clone()]).unwrap();
+        assert_ne!(block.hash, by_other_author.hash);
+
+        let mut later = header(BlockHash::zero());
+        later.timestamp += 1;
+        let later_block = Block::new(later, vec![transaction.clone()]).unwrap();
+        assert_ne!(block.hash, later_block.hash);
+
+        let mut next_term = header(BlockHash::zero());
+        next_term.term += 1;
+        let next_term_block = Block::new(next_term, vec![transaction]).unwrap();
+        assert_ne!(block.hash, next_term_block.hash);
+    }
+
+    #[test]
+    fn header_is_carried_through_serialization() {
+        let mut bc = Blockchain::new();
+        bc.add_transactions(
+            vec![Transaction::Insert(TransactionData::new(
+                "pedro", "algo1", "parcial", 10,
+            ))],
+            7,
+            3,
+        );
+        bc.add_transactions(
+            vec![Transaction::Remove(GradeKey::new(
+                "pedro", "algo1", "parcial",
+            ))],
+            9,
+            4,
+        );
+        let serialized = bc.serialize();
+        let parsed = Blockchain::parse(&mut serialized.split_whitespace()).unwrap();
+        let header = parsed.blocks[1].header();
+        assert_eq!(header.height(), 1);
+        assert_eq!(header.author(), 9);
+        assert_eq!(header.term(), 4);
+        assert_eq!(header.timestamp(), bc.blocks[1].header().timestamp());
+        assert_eq!(parsed.verify(), Ok(()));
+    }
+
+    #[test]
+    fn block_at_wrong_height_is_rejected() {
+        let mut bc = sample_chain();
+        let previous_hash = bc.blocks[0].hash;
+        let mut wrong = header(previous_hash);
+        wrong.height = 5;
+        let transaction = Transaction::Remove(GradeKey::new("juan", "algo1", "parcial"));
+        bc.blocks[1] = Block::new(wrong, vec![transaction]).unwrap();
+        assert_eq!(bc.verify(), Err(ChainError::WrongHeight { height: 1 }));
+    }
+
+    #[test]
+    fn state_follows_appended_blocks() {
+        let bc = sample_chain();
+        assert_eq!(
+            bc.state().get(&GradeKey::new("pedro", "algo1", "parcial")),
+            None
+        );
+        assert_eq!(
+            bc.state().get(&GradeKey::new("juan", "algo1", "parcial")),
+            Some(2)
+        );
+        assert_eq!(bc.state().len(), 1);
+        let serialized = bc.serialize();
+        let parsed = Blockchain::parse(&mut serialized.split_whitespace()).unwrap();
+        assert_eq!(parsed.state(), bc.state());
+    }
+
+    #[test]
+    fn display_lists_students_in_order() {
+        let mut bc = Blockchain::new();
+        bc.add_transactions(
+            vec![
+                Transaction::Insert(TransactionData::new("pedro", "algo1", "parcial", 10)),
+                Transaction::Insert(TransactionData::new("ana", "algo1", "parcial", 8)),
+            ],
+            1,
+            1,
+        );
+        assert_eq!(
+            bc.to_string(),
+            "Student ana [algo1 parcial] -> 8\nStudent pedro [algo1 parcial] -> 10\n"
+        );
+    }
+
+    #[test]
+    fn update_round_trips_with_its_previous_value() {
+        let update = Transaction::Update {
+            data: TransactionData::new("pedro", "algo1", "final", 8),
+            previous: 4,
+        };
+        let serialized = update.serialize();
+        assert_eq!(serialized, "update pedro algo1 final 4 8");
+        let parsed = Transaction::parse(&mut serialized.split_whitespace());
+        assert_eq!(parsed, Ok(update));
+    }
+
+    #[test]
+    fn grades_are_kept_per_course_and_evaluation() {
+        let mut bc = Blockchain::new();
+        bc.add_transactions(
+            vec![
+                Transaction::Insert(TransactionData::new("pedro", "algo1", "parcial", 4)),
+                Transaction::Insert(TransactionData::new("pedro", "algo1", "recuperatorio", 7)),
+                Transaction::Insert(TransactionData::new("pedro", "fisica", "final", 9)),
+            ],
+            1,
+            1,
+        );
+        assert_eq!(bc.state().len(), 3);
+        let key = GradeKey::new("pedro", "algo1", "parcial");
+        assert_eq!(bc.state().get(&key), Some(4));
+    }
+
+    #[test]
+    fn update_with_invalid_previous_score_is_invalid() {
+        let update = Transaction::Update {
+            data: TransactionData::new("pedro", "algo1", "final", 8),
+            previous: 0,
+        };
+        assert!(!update.is_valid());
+    }
+
+    fn grade(student: &str, score: u16) -> TransactionData {
+        TransactionData::new(student, "algo1", "parcial", score)
+    }
+
+    fn key(student: &str) -> GradeKey {
+        GradeKey::new(student, "algo1", "parcial")
+    }
+
+    #[test]
+    fn validate_accepts_writes_consistent_with_state() {
+        let bc = sample_chain();
+        let transactions = vec![
+            Transaction::Insert(grade("pedro", 6)),
+            Transaction::Update {
+                data: grade("juan", 5),
+                previous: 2,
+            },
+            Transaction::Remove(key("pedro")),
+        ];
+        assert_eq!(bc.validate(&transactions), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_remove_of_unknown_grade() {
+        let bc = sample_chain();
+        let transactions = vec![Transaction::Remove(key("pedro"))];
+        assert_eq!(
+            bc.validate(&transactions),
+            Err(ValidationError::UnknownGrade(key("pedro")))
+        );
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_insert() {
+        let bc = sample_chain();
+        let transactions = vec![Transaction::Insert(grade("juan", 9))];
+        assert_eq!(
+            bc.validate(&transactions),
+            Err(ValidationError::DuplicateGrade(key("juan")))
+        );
+        let in_same_block = vec![
+            Transaction::Insert(grade("ana", 9)),
+            Transaction::Insert(grade("ana", 8)),
+        ];
+        assert_eq!(
+            bc.validate(&in_same_block),
+            Err(ValidationError::DuplicateGrade(key("ana")))
+        );
+    }
+
+    #[test]
+    fn validate_rejects_update_with_stale_previous_value() {
+        let bc = sample_chain();
+        let transactions = vec![Transaction::Update {
+            data: grade("juan", 5),
+            previous: 3,
+        }];
+        assert_eq!(
+            bc.validate(&transactions),
+            Err(ValidationError::StalePrevious {
+                key: key("juan"),
+                current: 2
+            })
+        );
+    }
+
+    #[test]
+    fn validate_rejects_writes_that_cannot_form_a_block() {
+        let bc = sample_chain();
+        assert_eq!(bc.validate(&[]), Err(ValidationError::EmptyBlock));
+        let transactions = vec![
+            Transaction::Insert(grade("ana", 9)),
+            Transaction::Insert(grade("luis", 11)),
+        ];
+        assert_eq!(
+            bc.validate(&transactions),
+            Err(ValidationError::InvalidTransaction { index: 1 })
+        );
+    }
+
+    #[test]
+    fn validation_error_round_trips() {
+        let error = ValidationError::StalePrevious {
+            key: key("juan"),
+            current: 2,
+        };
+        let serialized = error.serialize();
+        let parsed = ValidationError::parse(&mut serialized.split_whitespace());
+        assert_eq!(parsed, Ok(error));
+    }
+
+    #[test]
+    fn malformed_score_is_an_error() {
+        let mut tokens = "insert pedro algo1 parcial abc".split_whitespace();
+        assert_eq!(
+            Transaction::parse(&mut tokens),
+            Err(TransactionParseError::Field(FieldError::Invalid {
+                field: "score",
+                value: "abc".to_owned()
+            }))
+        );
+    }
+
+    #[test]
+    fn truncated_and_unknown_transactions_are_errors() {
+        let mut tokens = "insert pedro algo1".split_whitespace();
+        assert_eq!(
+            Transaction::parse(&mut tokens),
+            Err(TransactionParseError::Field(FieldError::Missing(
+                "evaluation"
+            )))
+        );
+        let mut tokens = "insert pedro algo1 parcial 7 borrar".split_whitespace();
+        assert_eq!(
+            Transaction::parse_all(&mut tokens),
+            Err(TransactionParseError::UnknownAction("borrar".to_owned()))
+        );
+        assert_eq!(
+            Transaction::parse_all(&mut "".split_whitespace()),
+            Err(TransactionParseError::MissingAction)
+        );
+    }
+
+    #[test]
+    fn malformed_block_reports_its_height() {
+        let bc = sample_chain();
+        let broken = bc
+            .serialize()
+            .replace("insert juan algo1 parcial 2", "insert juan algo1 parcial x");
+        let err = Blockchain::parse(&mut broken.split_whitespace()).unwrap_err();
+        assert!(matches!(
+            err,
+            BlockchainParseError::Block {
+                height: 1,
+                source: BlockParseError::Transaction { index: 0, .. }
+            }
+        ));
+        let truncated = bc.serialize().replace(" end_blockchain", "");
+        assert_eq!(
+            Blockchain::parse(&mut truncated.split_whitespace()).unwrap_err(),
+            BlockchainParseError::MissingEnd
+        );
+    }
+
+    #[test]
+    fn block_errors_describe_the_problem() {
+        assert_eq!(
+            Block::new(header(BlockHash::zero()), vec![]).unwrap_err(),
+            BlockError::EmptyBlock
+        );
+        let invalid = Transaction::Insert(TransactionData::new("pedro", "algo1", "parcial", 11));
+        let err = Block::new(
+            header(BlockHash::zero()),
+            vec![Transaction::Insert(grade("ana", 7)), invalid],
+        )
+        .unwrap_err();
+        assert_eq!(err, BlockError::InvalidTransaction { index: 1 });
+        assert_eq!(err.to_string(), "Transaction 1 has invalid fields");
+    }
+
+    #[test]
+    fn adversarial_names_round_trip() {
+        let names = [
+            "Juan Pablo",
+            "end_blockchain",
+            "insert",
+            "remove",
+            "block",
+            "\"comillas\" y \\barras",
+            "Zoë Ñandú 日本",
+            "salto\nde línea",
+        ];
+        let mut bc = Blockchain::new();
+        for name in names.iter() {
+            let data = TransactionData::new(name, "algo 1", "parcial", 7);
+            bc.add_transactions(vec![Transaction::Insert(data)], 1, 1);
+        }
+        bc.add_transactions(
+            vec![Transaction::Remove(GradeKey::new(
+                "remove", "algo 1", "parcial",
+            ))],
+            1,
+            1,
+        );
+
+        let serialized = bc.serialize();
+        assert!(!serialized.contains('\n'));
+        let tokens = tokenize(&serialized).unwrap();
+        let parsed = Blockchain::parse(&mut tokens.iter().map(String::as_str)).unwrap();
+        assert_eq!(parsed.blocks(), bc.blocks());
+        assert_eq!(parsed.verify(), Ok(()));
+        assert_eq!(
+            parsed
+                .state()
+                .get(&GradeKey::new("Juan Pablo", "algo 1", "parcial")),
+            Some(7)
+        );
+        assert_eq!(
+            parsed
+                .state()
+                .get(&GradeKey::new("remove", "algo 1", "parcial")),
+            None
+        );
+    }
+
+    #[test]
+    fn state_as_of_a_past_block() {
+        let bc = sample_chain();
+        let pedro = GradeKey::new("pedro", "algo1", "parcial");
+        let juan = GradeKey::new("juan", "algo1", "parcial");
+        let first = bc.state_at(0).unwrap();
+        assert_eq!(first.get(&pedro), Some(10));
+        assert_eq!(first.get(&juan), None);
+        let second = bc.state_at(1).unwrap();
+        assert_eq!((second.get(&pedro), second.get(&juan)), (Some(10), Some(2)));
+        assert_eq!(bc.state_at(2).as_ref(), Some(bc.state()));
+        assert_eq!(bc.state_at(3), None);
+    }
+
+    #[test]
+    fn blocks_are_resolved_by_height_or_hash() {
+        let bc = sample_chain();
+        let hash = *bc.blocks()[1].hash();
+        assert_eq!(bc.resolve(&BlockRef::Hash(hash)), Some(1));
+        assert_eq!(bc.resolve(&BlockRef::Hash(BlockHash::zero())), None);
+        assert_eq!(bc.resolve(&BlockRef::Height(2)), Some(2));
+        assert_eq!(bc.resolve(&BlockRef::Height(3)), None);
+        let hex = hash.to_string();
+        assert_eq!(
+            BlockRef::parse(&mut std::iter::once(hex.as_str())),
+            Ok(BlockRef::Hash(hash))
+        );
+        assert_eq!(
+            BlockRef::parse(&mut std::iter::once("7")),
+            Ok(BlockRef::Height(7))
+        );
+        assert!(BlockRef::parse(&mut std::iter::once("abc")).is_err());
+    }
+
+    #[test]
+    fn student_history_lists_every_change_with_its_height() {
+        let mut bc = sample_chain();
+        bc.add_transactions(
+            vec![Transaction::Insert(TransactionData::new(
+                "pedro", "algo1", "parcial", 6,
+            ))],
+            1,
+            1,
+        );
+        let history: Vec<(usize, String)> = bc
+            .history_of("pedro")
+            .iter()
+            .map(|change| (change.height(), change.transaction().to_string()))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (0, "insert pedro [algo1 parcial] -> 10".to_owned()),
+                (2, "remove pedro [algo1 parcial]".to_owned()),
+                (3, "insert pedro [algo1 parcial] -> 6".to_owned()),
+            ]
+        );
+        assert!(bc.history_of("ana").is_empty());
+    }
+
+    #[test]
+    fn changes_between_two_heights() {
+        let bc = sample_chain();
+        let changes = bc.changes_between(0, 2).unwrap();
+        let heights: Vec<usize> = changes.iter().map(GradeChange::height).collect();
+        assert_eq!(heights, vec![1, 2]);
+        assert_eq!(bc.changes_between(1, 1), Some(vec![]));
+        assert_eq!(bc.changes_between(2, 1), None);
+        assert_eq!(bc.changes_between(0, 3), None);
+
+        let mut replayed = bc.state_at(0).unwrap();
+        for change in changes {
+            replayed.apply(change.transaction());
+        }
+        assert_eq!(&replayed, bc.state());
+    }
+
+    fn fork_of(base: &Blockchain, student: &str, term: TermType, blocks: usize) -> Blockchain {
+        let mut fork = base.clone();
+        for i in 0..blocks {
+            let evaluation = format!("final{}", i);
+            let data = TransactionData::new(student, "algo1", &evaluation, 8);
+            fork.add_transactions(vec![Transaction::Insert(data)], 2, term);
+        }
+        fork
+    }
+
+    #[test]
+    fn strict_extension_is_appended() {
+        let base = sample_chain();
+        let longer = fork_of(&base, "ana", 1, 2);
+        let mut local = base.clone();
+        assert_eq!(local.common_ancestor(&longer), Some(2));
+        assert_eq!(
+            local.reconcile(longer.clone(), 2),
+            Ok(Reconciliation::Extended { added: 2 })
+        );
+        assert_eq!(local.blocks(), longer.blocks());
+        assert_eq!(local.state(), longer.state());
+    }
+
+    #[test]
+    fn stale_chain_is_ignored() {
+        let mut local = fork_of(&sample_chain(), "ana", 1, 1);
+        let before = local.blocks().to_vec();
+        assert_eq!(
+            local.reconcile(sample_chain_prefix(&local, 2), 2),
+            Ok(Reconciliation::Stale)
+        );
+        assert_eq!(local.reconcile(local.clone(), 2), Ok(Reconciliation::Stale));
+        assert_eq!(local.blocks(), &before[..]);
+    }
+
+    fn sample_chain_prefix(chain: &Blockchain, len: usize) -> Blockchain {
+        let mut prefix = Blockchain::new();
+        for block in &chain.blocks()[..len] {
+            prefix.add_block(block.clone());
+        }
+        prefix
+    }
+
+    #[test]
+    fn fork_with_higher_term_wins() {
+        let base = sample_chain();
+        let mut local = fork_of(&base, "ana", 1, 3);
+        let newer_leader = fork_of(&base, "luis", 2, 1);
+        let result = local.reconcile(newer_leader.clone(), 2).unwrap();
+        match result {
+            Reconciliation::Fork {
+                ancestor,
+                adopted,
+                discarded,
+            } => {
+                assert_eq!(ancestor, Some(2));
+                assert!(adopted);
+                assert_eq!(discarded.len(), 3);
+                assert!(discarded.iter().all(|t| t.key().student() == "ana"));
+            }
+            other => panic!("expected fork, got {:?}", other),
+        }
+        assert_eq!(local.blocks(), newer_leader.blocks());
+    }
+
+    #[test]
+    fn fork_from_stale_leader_is_rejected() {
+        let base = sample_chain();
+        let mut local = fork_of(&base, "ana", 2, 1);
+        let stale = fork_of(&base, "luis", 1, 4);
+        let kept = local.blocks().to_vec();
+        assert!(matches!(
+            local.reconcile(stale, 2),
+            Ok(Reconciliation::Fork { adopted: false, .. })
+        ));
+        assert_eq!(local.blocks(), &kept[..]);
+    }
+
+    #[test]
+    fn fork_with_equal_term_prefers_longer_chain() {
+        let base = sample_chain();
+        let mut local = fork_of(&base, "ana", 1, 1);
+        let longer = fork_of(&base, "luis", 1, 2);
+        assert!(matches!(
+            local.reconcile(longer.clone(), 2),
+            Ok(Reconciliation::Fork { adopted: true, .. })
+        ));
+        assert_eq!(local.blocks(), longer.blocks());
+
+        let mut disjoint = Blockchain::new();
+        disjoint.add_transactions(vec![Transaction::Insert(grade("eva", 5))], 3, 1);
+        assert_eq!(local.common_ancestor(&disjoint), None);
+        assert!(matches!(
+            local.reconcile(disjoint, 2),
+            Ok(Reconciliation::Fork {
+                ancestor: None,
+                adopted: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn fork_from_an_unseen_term_is_rejected() {
+        let base = sample_chain();
+        let mut local = fork_of(&base, "ana", 1, 2);
+        let forged = fork_of(&base, "luis", 7, 1);
+        let kept = local.blocks().to_vec();
+        assert_eq!(
+            local.reconcile(forged.clone(), 2),
+            Err(ChainError::UnknownTerm { height: 3, term: 7 })
+        );
+        assert_eq!(local.blocks(), &kept[..]);
+        assert!(matches!(
+            local.reconcile(forged, 7),
+            Ok(Reconciliation::Fork { adopted: true, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_incoming_chain_is_rejected() {
+        let mut local = sample_chain();
+        let mut tampered = fork_of(&local, "ana", 9, 1);
+        tampered.blocks[1].hash = BlockHash::zero();
+        assert_eq!(
+            local.reconcile(tampered, 2),
+            Err(ChainError::HashMismatch { height: 1 })
+        );
+        assert_eq!(local.height(), 3);
+    }
+
+    #[test]
+    fn append_accepts_only_blocks_linked_to_the_tip() {
+        let base = sample_chain();
+        let leader = fork_of(&base, "ana", 1, 2);
+        let mut follower = base;
+        assert_eq!(
+            follower.append(leader.blocks()[4].clone()),
+            Err(ChainError::WrongHeight { height: 3 })
+        );
+        follower.append(leader.blocks()[3].clone()).unwrap();
+        follower.append(leader.blocks()[4].clone()).unwrap();
+        assert_eq!(follower.blocks(), leader.blocks());
+        assert_eq!(follower.state(), leader.state());
+
+        let other = fork_of(&sample_chain(), "luis", 1, 1);
+        let mut stale = sample_chain();
+        stale.blocks.pop();
+        let mut tampered = other.blocks()[3].clone();
+        tampered.header.height = 2;
+        assert_eq!(
+            stale.append(tampered),
+            Err(ChainError::HashMismatch { height: 2 })
+        );
+    }
+
+    #[test]
+    fn segments_are_reconciled_without_the_full_chain() {
+        let local = sample_chain();
+        let leader = fork_of(&local, "ana", 1, 2);
+        let mut follower = local.clone();
+        assert_eq!(
+            follower.reconcile_segment(2, leader.blocks()[2..].to_vec(), 2),
+            Ok(Reconciliation::Extended { added: 2 })
+        );
+        assert_eq!(follower.blocks(), leader.blocks());
+
+        let newer = fork_of(&local, "luis", 2, 1);
+        let mut follower = leader.clone();
+        assert!(matches!(
+            follower.reconcile_segment(3, newer.blocks()[3..].to_vec(), 2),
+            Ok(Reconciliation::Fork {
+                ancestor: Some(2),
+                adopted: true,
+                ..
+            })
+        ));
+        assert_eq!(follower.blocks(), newer.blocks());
+        assert_eq!(follower.state(), newer.state());
+
+        let mut short = sample_chain_prefix(&local, 1);
+        assert_eq!(
+            short.reconcile_segment(3, newer.blocks()[3..].to_vec(), 2),
+            Err(ChainError::BrokenLink { height: 3 })
+        );
+        assert_eq!(
+            short.reconcile_segment(1, newer.blocks()[3..].to_vec(), 2),
+            Err(ChainError::WrongHeight { height: 1 })
+        );
     }
 }
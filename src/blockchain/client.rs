@@ -1,9 +1,12 @@
 use std::io;
 use std::sync::mpsc::channel;
 
+use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::config::Config;
 use crate::blockchain::lock::CentralizedLock;
-use crate::blockchain::peer::PeerIdType;
+use crate::blockchain::storage::{ChainStore, TermStore};
 use crate::communication::dispatcher::Dispatcher;
+use crate::communication::handshake::ChainTip;
 use crate::handler::connection_handler::ConnectionHandler;
 use crate::handler::input_handler::InputProcessor;
 use crate::handler::leader_handler::LeaderHandler;
@@ -15,21 +18,37 @@ use std::sync::{Arc, Condvar, Mutex};
 
 #[derive(Debug)]
 pub struct Client {
-    id: PeerIdType,
+    config: Config,
 }
 
 #[allow(clippy::mutex_atomic)]
 impl Client {
-    pub fn new(id: u32) -> Self {
-        Client { id }
+    pub fn new(config: Config) -> Self {
+        Client { config }
     }
 
-    pub fn run<T: 'static + Read + Send>(
-        &mut self,
-        source: T,
-        port_from: u16,
-        port_to: u16,
-    ) -> io::Result<()> {
+    pub fn run<T: 'static + Read + Send>(&mut self, source: T) -> io::Result<()> {
+        let (store, blockchain) = match self.config.data_dir() {
+            Some(dir) => {
+                let (store, blockchain) = ChainStore::open(dir)?;
+                info!(
+                    "Loaded {} blocks from {}",
+                    blockchain.height(),
+                    dir.display()
+                );
+                (Some(store), blockchain)
+            }
+            None => (None, Blockchain::new()),
+        };
+        let (term_store, term) = match self.config.data_dir() {
+            Some(dir) => {
+                let (store, term) = TermStore::open(dir)?;
+                (Some(store), term)
+            }
+            None => (None, 0),
+        };
+
+        let tip = Arc::new(Mutex::new(ChainTip::of(&blockchain)));
         let (leader_handler_sender, leader_handler_receiver) = channel();
         let (peer_handler_sender, peer_handler_receiver) = channel();
         let (message_handler_sender, message_handler_receiver) = channel();
@@ -41,15 +60,17 @@ impl Client {
             peer_handler_sender.clone(),
             output_sender.clone(),
             leader_notify.clone(),
-            self.id,
+            &self.config,
+            term_store,
+            term,
         );
 
-        let lock = CentralizedLock::new();
+        let lock = CentralizedLock::new(self.config.lock_expiration());
         let lock_notify = Arc::new((Mutex::new(lock), Condvar::new()));
         let lock_handler = LockProcessor::new(peer_handler_sender.clone(), lock_notify.clone());
 
         let dispatcher = Dispatcher::new(
-            self.id,
+            self.config.node_id(),
             peer_handler_sender,
             message_handler_sender,
             leader_handler_sender.clone(),
@@ -57,16 +78,30 @@ impl Client {
             lock_handler,
         );
 
-        let connection_handler = ConnectionHandler::new(port_from, port_to, dispatcher.clone());
+        let connection_handler = ConnectionHandler::new(&self.config, dispatcher.clone())?;
+        let listen_addr = connection_handler.local_addr();
+        info!("Listening on {}", listen_addr);
+        if let Some(discovery_addr) = connection_handler.discovery_addr() {
+            info!("Discovering peers on {}", discovery_addr);
+        }
 
         let message_handler = MessageHandler::new(
-            self.id,
+            self.config.node_id(),
             message_handler_receiver,
             dispatcher.clone(),
             leader_notify,
+            blockchain,
+            store,
+            tip.clone(),
         );
 
-        let peer_handler = PeerHandler::new(self.id, peer_handler_receiver, dispatcher.clone());
+        let peer_handler = PeerHandler::new(
+            &self.config,
+            listen_addr,
+            tip,
+            peer_handler_receiver,
+            dispatcher.clone(),
+        );
 
         let input_handler = InputProcessor::new(output_receiver, dispatcher);
         input_handler.run(source);
@@ -0,0 +1,556 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use crate::blockchain::peer::PeerIdType;
+use crate::communication::serialization::WireFormat;
+use crate::logging::LogLevel;
+
+pub const USAGE: &str = "\
+Uso: blockchain [opciones] [directorio de datos] [text|binary]
+
+Opciones:
+  --config ARCHIVO           lee las opciones de un archivo `clave = valor`
+  --id N                     id del nodo (por defecto el pid)
+  --bind IP                  dirección en la que se escucha (127.0.0.1)
+  --ports DESDE-HASTA        puertos en los que escuchar y buscar nodos (9000-9009)
+  --seeds HOST:PUERTO,...    nodos a los que conectarse en lugar de recorrer los puertos
+  --discovery IP             anuncia el nodo por UDP a un grupo multicast o broadcast
+  --discovery-ports DESDE-HASTA
+                             puertos UDP de los anuncios, uno por nodo de la máquina (9900-9909)
+  --cluster NOMBRE           sólo se conecta a nodos anunciados con el mismo nombre (blockchain)
+  --announce-interval T      cada cuánto se anuncia el nodo (2s)
+  --data-dir DIR             directorio donde se guarda la cadena
+  --format text|binary       formato de mensajes entre nodos (binary)
+  --reply-malformed BOOL     responder con error a los mensajes ilegibles (true)
+  --election-timeout T       espera de una elección, en s o ms (5s)
+  --lock-expiration T        duración del lock de escritura (5s)
+  --handshake-timeout T      espera del handshake de una conexión nueva (5s)
+  --heartbeat-interval T     cada cuánto se avisa a un par que el nodo sigue vivo (1s)
+  --failure-timeout T        silencio tras el que se da a un par por caído (5s)
+  --reconnect-delay T        espera antes de volver a llamar a un par caído (500ms)
+  --reconnect-max-delay T    tope de la espera, que se duplica en cada intento (30s)
+  --log-level NIVEL          error, warn, info o debug (debug)
+  --help                     muestra esta ayuda";
+
+/// Configuración de un nodo. Se arma con los valores por defecto, pisados por el archivo de
+/// configuración y después por los argumentos.
+#[derive(Debug, Clone)]
+pub struct Config {
+    node_id: PeerIdType,
+    bind_addr: IpAddr,
+    ports: RangeInclusive<u16>,
+    seeds: Vec<String>,
+    discovery_group: Option<Ipv4Addr>,
+    discovery_ports: RangeInclusive<u16>,
+    cluster: String,
+    announce_interval: Duration,
+    data_dir: Option<PathBuf>,
+    wire_format: WireFormat,
+    reply_malformed: bool,
+    election_timeout: Duration,
+    lock_expiration: Duration,
+    handshake_timeout: Duration,
+    heartbeat_interval: Duration,
+    failure_timeout: Duration,
+    reconnect_delay: Duration,
+    reconnect_max_delay: Duration,
+    log_level: LogLevel,
+}
+
+/// Motivo por el que no se puede armar la configuración
+#[derive(Debug)]
+pub enum ConfigError {
+    Help,
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedArgument(String),
+    Invalid {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+    File {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// Error en una línea del archivo de configuración
+    Line {
+        path: PathBuf,
+        line: usize,
+        error: Box<ConfigError>,
+    },
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::File { error, .. } => Some(error),
+            ConfigError::Line { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Help => write!(f, "{}", USAGE),
+            ConfigError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            ConfigError::MissingValue(option) => write!(f, "missing value for '{}'", option),
+            ConfigError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            ConfigError::Invalid {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value '{}' for '{}': expected {}",
+                value, option, expected
+            ),
+            ConfigError::File { path, error } => {
+                write!(f, "cannot read {}: {}", path.display(), error)
+            }
+            ConfigError::Line { path, line, error } => {
+                write!(f, "{}:{}: {}", path.display(), line, error)
+            }
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            node_id: process::id(),
+            bind_addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            ports: 9000..=9009,
+            seeds: vec![],
+            discovery_group: None,
+            discovery_ports: 9900..=9909,
+            cluster: "blockchain".to_owned(),
+            announce_interval: Duration::from_secs(2),
+            data_dir: None,
+            wire_format: WireFormat::Binary,
+            reply_malformed: true,
+            election_timeout: Duration::from_secs(5),
+            lock_expiration: Duration::from_secs(5),
+            handshake_timeout: Duration::from_secs(5),
+            heartbeat_interval: Duration::from_secs(1),
+            failure_timeout: Duration::from_secs(5),
+            reconnect_delay: Duration::from_millis(500),
+            reconnect_max_delay: Duration::from_secs(30),
+            log_level: LogLevel::Debug,
+        }
+    }
+}
+
+impl Config {
+    /// Arma la configuración a partir de los argumentos, sin el nombre del programa.
+    /// `--config` se aplica primero para que el resto de los argumentos lo pise.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let mut options = vec![];
+        let mut positional = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(ConfigError::Help);
+            }
+            let option = match arg.strip_prefix("--") {
+                Some(option) => option,
+                None => {
+                    positional.push(arg);
+                    continue;
+                }
+            };
+            let (option, value) = match option.split_once('=') {
+                Some((option, value)) => (option.to_owned(), value.to_owned()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+                    (option.to_owned(), value)
+                }
+            };
+            options.push((option, value));
+        }
+
+        let mut config = Config::default();
+        for (_, path) in options.iter().filter(|(option, _)| option == "config") {
+            config.load_file(Path::new(path))?;
+        }
+        for (option, value) in options.iter().filter(|(option, _)| option != "config") {
+            config.set(option, value)?;
+        }
+        // Forma histórica: `blockchain <directorio> [formato]`
+        let mut positional = positional.into_iter();
+        if let Some(dir) = positional.next() {
+            config.set("data-dir", &dir)?;
+        }
+        if let Some(format) = positional.next() {
+            config.set("format", &format)?;
+        }
+        if let Some(extra) = positional.next() {
+            return Err(ConfigError::UnexpectedArgument(extra));
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Revisa las reglas que relacionan varias opciones. `set` valida cada valor por separado,
+    /// así que esto se llama una vez aplicados el archivo y los argumentos.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // Con un silencio más corto que el intervalo se darían por caídos pares sanos
+        if self.failure_timeout <= self.heartbeat_interval {
+            return Err(ConfigError::Invalid {
+                option: "failure-timeout".to_owned(),
+                value: format!("{:?}", self.failure_timeout),
+                expected: "a duration longer than the heartbeat interval",
+            });
+        }
+        Ok(())
+    }
+
+    /// Aplica un archivo de líneas `clave = valor`; las claves son las de las opciones sin
+    /// `--` y `#` empieza un comentario
+    pub fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|error| ConfigError::File {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        self.load_str(&contents)
+            .map_err(|(line, error)| ConfigError::Line {
+                path: path.to_path_buf(),
+                line,
+                error: Box::new(error),
+            })
+    }
+
+    fn load_str(&mut self, contents: &str) -> Result<(), (usize, ConfigError)> {
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (option, value) = line
+                .split_once('=')
+                .ok_or_else(|| (number + 1, ConfigError::MissingValue(line.to_owned())))?;
+            self.set(option.trim(), value.trim())
+                .map_err(|error| (number + 1, error))?;
+        }
+        Ok(())
+    }
+
+    /// Cambia una opción validando su valor; las reglas entre opciones las revisa `validate`
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |expected| ConfigError::Invalid {
+            option: option.to_owned(),
+            value: value.to_owned(),
+            expected,
+        };
+        match option {
+            "id" => {
+                self.node_id = value
+                    .parse()
+                    .ok()
+                    .filter(|id| *id != 0)
+                    .ok_or_else(|| invalid("a positive node id"))?
+            }
+            "bind" => self.bind_addr = value.parse().map_err(|_| invalid("an IP address"))?,
+            "ports" => self.ports = parse_ports(value).ok_or_else(|| invalid("FROM-TO ports"))?,
+            "seeds" => {
+                self.seeds = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|seed| !seed.is_empty())
+                    .map(|seed| valid_seed(seed).then(|| seed.to_owned()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid("a list of HOST:PORT"))?
+            }
+            "discovery" => {
+                let group: Ipv4Addr = value
+                    .parse()
+                    .ok()
+                    .filter(|ip: &Ipv4Addr| !ip.is_unspecified())
+                    .ok_or_else(|| invalid("a multicast or broadcast IPv4 address"))?;
+                self.discovery_group = Some(group)
+            }
+            "discovery-ports" => {
+                self.discovery_ports = parse_ports(value).ok_or_else(|| invalid("FROM-TO ports"))?
+            }
+            "cluster" => {
+                if value.is_empty() || value.contains(char::is_whitespace) {
+                    return Err(invalid("a name without spaces"));
+                }
+                self.cluster = value.to_owned()
+            }
+            "announce-interval" => {
+                self.announce_interval =
+                    parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "data-dir" => {
+                if value.is_empty() {
+                    return Err(invalid("a directory"));
+                }
+                self.data_dir = Some(PathBuf::from(value))
+            }
+            "format" => {
+                self.wire_format =
+                    WireFormat::parse(value).ok_or_else(|| invalid("text or binary"))?
+            }
+            "reply-malformed" => {
+                self.reply_malformed = value.parse().map_err(|_| invalid("true or false"))?
+            }
+            "election-timeout" => {
+                self.election_timeout =
+                    parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "lock-expiration" => {
+                self.lock_expiration = parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "handshake-timeout" => {
+                self.handshake_timeout =
+                    parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "heartbeat-interval" => {
+                self.heartbeat_interval =
+                    parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "failure-timeout" => {
+                self.failure_timeout = parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "reconnect-delay" => {
+                self.reconnect_delay = parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "reconnect-max-delay" => {
+                self.reconnect_max_delay =
+                    parse_duration(value).ok_or_else(|| invalid("a duration"))?
+            }
+            "log-level" => {
+                self.log_level =
+                    LogLevel::parse(value).ok_or_else(|| invalid("error, warn, info or debug"))?
+            }
+            other => return Err(ConfigError::UnknownOption(other.to_owned())),
+        }
+        Ok(())
+    }
+
+    pub fn node_id(&self) -> PeerIdType {
+        self.node_id
+    }
+
+    pub fn bind_addr(&self) -> IpAddr {
+        self.bind_addr
+    }
+
+    pub fn ports(&self) -> RangeInclusive<u16> {
+        self.ports.clone()
+    }
+
+    pub fn seeds(&self) -> &[String] {
+        &self.seeds
+    }
+
+    /// Grupo al que se mandan los anuncios; sin él no hay descubrimiento por UDP
+    pub fn discovery_group(&self) -> Option<Ipv4Addr> {
+        self.discovery_group
+    }
+
+    pub fn discovery_ports(&self) -> RangeInclusive<u16> {
+        self.discovery_ports.clone()
+    }
+
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    pub fn announce_interval(&self) -> Duration {
+        self.announce_interval
+    }
+
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
+
+    pub fn wire_format(&self) -> WireFormat {
+        self.wire_format
+    }
+
+    /// Si se responde con un error a los mensajes de otros nodos que no se pueden interpretar
+    pub fn reply_malformed(&self) -> bool {
+        self.reply_malformed
+    }
+
+    pub fn election_timeout(&self) -> Duration {
+        self.election_timeout
+    }
+
+    pub fn lock_expiration(&self) -> Duration {
+        self.lock_expiration
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        self.handshake_timeout
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+
+    pub fn failure_timeout(&self) -> Duration {
+        self.failure_timeout
+    }
+
+    pub fn reconnect_delay(&self) -> Duration {
+        self.reconnect_delay
+    }
+
+    pub fn reconnect_max_delay(&self) -> Duration {
+        self.reconnect_max_delay
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+}
+
+fn parse_ports(value: &str) -> Option<RangeInclusive<u16>> {
+    let (from, to) = value.split_once('-')?;
+    let from: u16 = from.trim().parse().ok()?;
+    let to: u16 = to.trim().parse().ok()?;
+    (from != 0 && from <= to).then_some(from..=to)
+}
+
+/// `5`, `5s` o `500ms`; una espera de cero no tiene sentido
+fn parse_duration(value: &str) -> Option<Duration> {
+    let duration = match value.strip_suffix("ms") {
+        Some(millis) => Duration::from_millis(millis.parse().ok()?),
+        None => Duration::from_secs(value.strip_suffix('s').unwrap_or(value).parse().ok()?),
+    };
+    (duration > Duration::from_secs(0)).then_some(duration)
+}
+
+fn valid_seed(seed: &str) -> bool {
+    match seed.rsplit_once(':') {
+        Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok_and(|p| p != 0),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn arguments_override_defaults() {
+        let config = Config::from_args(args(
+            "--id 7 --bind 0.0.0.0 --ports=9100-9104 --election-timeout 500ms \
+             --lock-expiration 2 --log-level warn datos/a text",
+        ))
+        .unwrap();
+        assert_eq!(config.node_id(), 7);
+        assert_eq!(config.bind_addr(), IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(config.ports(), 9100..=9104);
+        assert_eq!(config.election_timeout(), Duration::from_millis(500));
+        assert_eq!(config.lock_expiration(), Duration::from_secs(2));
+        assert_eq!(config.handshake_timeout(), Duration::from_secs(5));
+        assert_eq!(config.log_level(), LogLevel::Warn);
+        assert_eq!(config.data_dir(), Some(Path::new("datos/a")));
+        assert_eq!(config.wire_format(), WireFormat::Text);
+        assert!(config.reply_malformed());
+    }
+
+    #[test]
+    fn config_file_is_overridden_by_arguments() {
+        let path = std::env::temp_dir().join(format!("node_{}.conf", process::id()));
+        fs::write(
+            &path,
+            "# nodo de prueba\nid = 3\nseeds = 10.0.0.1:9000, nodo-b:9001\n\n\
+             handshake-timeout = 1s # corto\nlog-level = info\n",
+        )
+        .unwrap();
+        let config = Config::from_args(args(&format!(
+            "--log-level error --config {}",
+            path.display()
+        )))
+        .unwrap();
+        assert_eq!(config.node_id(), 3);
+        assert_eq!(config.seeds(), ["10.0.0.1:9000", "nodo-b:9001"]);
+        assert_eq!(config.handshake_timeout(), Duration::from_secs(1));
+        assert_eq!(config.log_level(), LogLevel::Error);
+
+        // El archivo solo baja el timeout por debajo del intervalo por defecto
+        fs::write(&path, "failure-timeout = 500ms\n").unwrap();
+        let error = Config::from_args(args(&format!("--config {}", path.display()))).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { .. }));
+
+        fs::write(&path, "id = 3\nports = 9005-9000\n").unwrap();
+        let error = Config::from_args(args(&format!("--config {}", path.display()))).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}:2: invalid value '9005-9000' for 'ports': expected FROM-TO ports",
+                path.display()
+            )
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let error = |line| Config::from_args(args(line)).unwrap_err().to_string();
+        assert_eq!(
+            error("--id 0"),
+            "invalid value '0' for 'id': expected a positive node id"
+        );
+        assert_eq!(
+            error("--election-timeout 0ms"),
+            "invalid value '0ms' for 'election-timeout': expected a duration"
+        );
+        assert_eq!(
+            error("--seeds 10.0.0.1"),
+            "invalid value '10.0.0.1' for 'seeds': expected a list of HOST:PORT"
+        );
+        assert_eq!(
+            Config::default()
+                .set("cluster", "a b")
+                .unwrap_err()
+                .to_string(),
+            "invalid value 'a b' for 'cluster': expected a name without spaces"
+        );
+        assert_eq!(
+            error("--discovery 0.0.0.0"),
+            "invalid value '0.0.0.0' for 'discovery': expected a multicast or broadcast IPv4 address"
+        );
+        assert_eq!(
+            error("--heartbeat-interval 2s --failure-timeout 1500ms"),
+            "invalid value '1.5s' for 'failure-timeout': \
+             expected a duration longer than the heartbeat interval"
+        );
+        let mut config = Config::default();
+        config.set("failure-timeout", "500ms").unwrap();
+        assert!(config.validate().is_err());
+        config.set("heartbeat-interval", "100ms").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(error("--bind"), "missing value for '--bind'");
+        assert_eq!(error("--port 9000"), "unknown option 'port'");
+        assert_eq!(error("a text b"), "unexpected argument 'b'");
+        assert_eq!(
+            error("--config /no/existe").split(':').next(),
+            Some("cannot read /no/existe")
+        );
+        assert!(matches!(
+            Config::from_args(args("--id 2 --help")),
+            Err(ConfigError::Help)
+        ));
+    }
+}
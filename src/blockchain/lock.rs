@@ -2,8 +2,6 @@ use crate::blockchain::lock::LockResult::{Acquired, Locked, ReleaseFailed, Relea
 use crate::blockchain::peer::PeerIdType;
 use std::time::{SystemTime, Duration};
 
-const LOCK_EXPIRATION_TIME: u64 = 5;
-
 #[derive(PartialEq)]
 pub enum LockResult {
     Acquired,
@@ -32,7 +30,7 @@ pub trait Lock {
 pub struct CentralizedLock {
     peer_id: Option<PeerIdType>,
     lock_time: SystemTime,
-    expiration_time: u64,
+    expiration_time: Duration,
 }
 
 impl Lock for CentralizedLock {
@@ -71,29 +69,24 @@ impl Lock for CentralizedLock {
 
     fn lock_expired(&self) -> bool {
         if let Ok(elapsed) = SystemTime::now().duration_since(self.lock_time) {
-            elapsed.as_secs() > self.expiration_time
+            elapsed > self.expiration_time
         } else {
             true
         }
     }
 
     fn get_duration(&self) -> Duration {
-        Duration::from_secs(self.expiration_time)
+        self.expiration_time
     }
 }
 
 impl CentralizedLock {
-    pub fn new() -> CentralizedLock {
+    /// Un lock tomado se libera solo después de `expiration_time`
+    pub fn new(expiration_time: Duration) -> CentralizedLock {
         CentralizedLock {
             peer_id: None,
             lock_time: SystemTime::UNIX_EPOCH,
-            expiration_time: LOCK_EXPIRATION_TIME,
+            expiration_time,
         }
     }
 }
-
-impl Default for CentralizedLock {
-    fn default() -> Self {
-        Self::new()
-    }
-}
@@ -1,4 +1,11 @@
+#[allow(clippy::module_inception)]
 pub mod blockchain;
 pub mod client;
+pub mod config;
+pub mod hash;
 pub mod lock;
+pub mod parse_error;
 pub mod peer;
+pub mod reconnect;
+pub mod state;
+pub mod storage;
@@ -0,0 +1,143 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Error en un campo individual del protocolo de texto
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldError {
+    Missing(&'static str),
+    Invalid { field: &'static str, value: String },
+}
+
+impl Error for FieldError {}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldError::Missing(field) => write!(f, "missing {}", field),
+            FieldError::Invalid { field, value } => write!(f, "invalid {} '{}'", field, value),
+        }
+    }
+}
+
+/// Toma el próximo token, que debe existir
+pub fn next_field<'a>(
+    tokens: &mut dyn Iterator<Item = &'a str>,
+    field: &'static str,
+) -> Result<&'a str, FieldError> {
+    tokens.next().ok_or(FieldError::Missing(field))
+}
+
+/// Toma el próximo token y lo convierte al tipo pedido
+pub fn parse_field<T: FromStr>(
+    tokens: &mut dyn Iterator<Item = &str>,
+    field: &'static str,
+) -> Result<T, FieldError> {
+    let value = next_field(tokens, field)?;
+    value.parse::<T>().map_err(|_| FieldError::Invalid {
+        field,
+        value: value.to_owned(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionParseError {
+    MissingAction,
+    UnknownAction(String),
+    Field(FieldError),
+}
+
+impl Error for TransactionParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransactionParseError::Field(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TransactionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionParseError::MissingAction => write!(f, "missing transaction action"),
+            TransactionParseError::UnknownAction(action) => {
+                write!(f, "unknown transaction action '{}'", action)
+            }
+            TransactionParseError::Field(err) => write!(f, "transaction {}", err),
+        }
+    }
+}
+
+impl From<FieldError> for TransactionParseError {
+    fn from(err: FieldError) -> Self {
+        TransactionParseError::Field(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockParseError {
+    Field(FieldError),
+    Transaction {
+        index: usize,
+        source: TransactionParseError,
+    },
+}
+
+impl Error for BlockParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlockParseError::Field(err) => Some(err),
+            BlockParseError::Transaction { source, .. } => Some(source),
+        }
+    }
+}
+
+impl fmt::Display for BlockParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockParseError::Field(err) => write!(f, "block {}", err),
+            BlockParseError::Transaction { index, source } => {
+                write!(f, "block transaction {}: {}", index, source)
+            }
+        }
+    }
+}
+
+impl From<FieldError> for BlockParseError {
+    fn from(err: FieldError) -> Self {
+        BlockParseError::Field(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockchainParseError {
+    MissingEnd,
+    UnexpectedToken(String),
+    Block {
+        height: usize,
+        source: BlockParseError,
+    },
+}
+
+impl Error for BlockchainParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlockchainParseError::Block { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BlockchainParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockchainParseError::MissingEnd => write!(f, "blockchain without end_blockchain"),
+            BlockchainParseError::UnexpectedToken(token) => {
+                write!(f, "unexpected '{}' in blockchain", token)
+            }
+            BlockchainParseError::Block { height, source } => {
+                write!(f, "blockchain at height {}: {}", height, source)
+            }
+        }
+    }
+}
@@ -1,77 +1,198 @@
 use std::io;
 use std::io::Write;
-use std::net::TcpStream;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
+use crate::blockchain::config::Config;
 use crate::communication::client_event::ClientEvent;
-use crate::communication::client_event::Message;
+use crate::communication::client_event::{ClientMessage, ErrorMessage, Message};
 use crate::communication::dispatcher::Dispatcher;
-use crate::communication::serialization::LineReader;
+use crate::communication::serialization::{ReadError, WireFormat};
 
 pub type PeerIdType = u32;
 
 #[derive(Debug)]
 pub struct Peer {
     id: PeerIdType,
+    listen_addr: SocketAddr,
+    /// La conexión la abrió el otro extremo
+    incoming: bool,
+    /// Se apaga cuando deja de llegar lo que manda el par
+    connected: Arc<AtomicBool>,
+    stream: TcpStream,
     recv_thread: Option<thread::JoinHandle<()>>,
     send_thread: Option<thread::JoinHandle<()>>,
     sender: Option<Sender<ClientEvent>>,
 }
 
 impl Peer {
-    pub fn new(id: u32, stream: TcpStream, dispatcher: Dispatcher) -> Self {
-        let stream_clone = stream.try_clone().unwrap();
+    pub fn new(
+        id: u32,
+        listen_addr: SocketAddr,
+        stream: TcpStream,
+        incoming: bool,
+        format: WireFormat,
+        config: &Config,
+        dispatcher: Dispatcher,
+    ) -> io::Result<Self> {
+        // Un par que no manda nada, ni siquiera heartbeats, se da por caído
+        let failure_timeout = config.failure_timeout();
+        stream.set_read_timeout(Some(failure_timeout))?;
+        stream.set_write_timeout(Some(failure_timeout))?;
+        let stream_clone = stream.try_clone()?;
+        let recv_stream = stream.try_clone()?;
         let (local_sender, receiver) = channel();
+        let connected = Arc::new(AtomicBool::new(true));
 
+        let reply_malformed = config.reply_malformed();
+        let recv_connected = connected.clone();
         let recv_thread = Some(thread::spawn(move || {
-            Peer::recv_messages(id, stream, dispatcher).unwrap();
+            Peer::recv_messages(
+                id,
+                recv_stream,
+                format,
+                reply_malformed,
+                failure_timeout,
+                dispatcher,
+                recv_connected,
+            )
+            .unwrap();
         }));
 
+        let heartbeat_interval = config.heartbeat_interval();
         let send_thread = Some(thread::spawn(move || {
-            Peer::send_messages(stream_clone, receiver).unwrap();
+            let sent = Peer::send_messages(stream_clone, format, heartbeat_interval, receiver);
+            if let Err(err) = sent {
+                warn!("Could not send to {}: {}", id, err);
+            }
         }));
-        Peer {
+        Ok(Peer {
             id,
+            listen_addr,
+            incoming,
+            connected,
+            stream,
             recv_thread,
             send_thread,
             sender: Some(local_sender),
-        }
+        })
     }
 
     fn recv_messages(
         peer_id: u32,
         stream: TcpStream,
+        format: WireFormat,
+        reply_malformed: bool,
+        failure_timeout: Duration,
         dispatcher: Dispatcher,
+        connected: Arc<AtomicBool>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let message_reader = LineReader::new(stream);
+        let message_reader = format.reader::<_, Message>(stream);
         for message in message_reader {
-            let event = ClientEvent::PeerMessage { message, peer_id };
-            dispatcher.dispatch(event)?;
+            match message {
+                Ok(Message::Common(ClientMessage::Heartbeat)) => {}
+                Ok(Message::Common(ClientMessage::DuplicateConnection)) => {
+                    // El par se queda con la otra conexión; no es una desconexión
+                    info!("Peer {} closed a duplicate connection", peer_id);
+                    connected.store(false, Ordering::SeqCst);
+                    return Ok(());
+                }
+                Ok(message) => {
+                    let event = ClientEvent::PeerMessage { message, peer_id };
+                    dispatcher.dispatch(event)?;
+                }
+                Err(ReadError::Io(err))
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    warn!(
+                        "Peer {} suspected: nothing received in {:?}",
+                        peer_id, failure_timeout
+                    );
+                }
+                Err(ReadError::Io(err)) => {
+                    warn!("Connection with {} failed: {}", peer_id, err);
+                }
+                Err(ReadError::Malformed(bad)) => {
+                    // Un mensaje inválido no corta la conexión; opcionalmente se avisa al emisor
+                    warn!("Malformed message from {}: {}", peer_id, bad);
+                    if !reply_malformed {
+                        continue;
+                    }
+                    let error = ErrorMessage::MalformedMessageError(bad.to_string());
+                    let event = ClientEvent::PeerMessage {
+                        message: Message::Common(ClientMessage::ErrorResponse {
+                            request_id: None,
+                            error,
+                        }),
+                        peer_id,
+                    };
+                    dispatcher.peer_sender.send(event)?;
+                }
+            }
         }
         warn!("No more events from {}", peer_id);
+        connected.store(false, Ordering::SeqCst);
         dispatcher.dispatch(ClientEvent::PeerDisconnected { peer_id })?;
         Ok(())
     }
 
+    /// Manda los mensajes encolados y un heartbeat cada vez que la conexión queda ociosa
     fn send_messages(
         mut stream: TcpStream,
+        format: WireFormat,
+        heartbeat_interval: Duration,
         receiver: Receiver<ClientEvent>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        for event in receiver {
-            let buf = event.serialize();
-            stream.write_all(buf.as_bytes())?;
+    ) -> io::Result<()> {
+        loop {
+            let message = match receiver.recv_timeout(heartbeat_interval) {
+                Ok(ClientEvent::UserInput { message }) => message,
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => Message::Common(ClientMessage::Heartbeat),
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            if let Err(err) = stream.write_all(&format.encode(&message)) {
+                // Cortar la conexión despierta al hilo que lee, que avisa la desconexión
+                stream.shutdown(Shutdown::Both).ok();
+                return Err(err);
+            }
         }
-        Ok(())
+    }
+
+    pub fn id(&self) -> PeerIdType {
+        self.id
+    }
+
+    /// Dirección en la que el par acepta conexiones, según su handshake
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen_addr
+    }
+
+    pub fn is_incoming(&self) -> bool {
+        self.incoming
+    }
+
+    /// Falso desde que la conexión se cortó, aunque el par todavía no se haya sacado
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Cierra una conexión repetida con un par avisándole, para que no la tome como caída
+    pub fn close_duplicate(stream: &mut TcpStream, format: WireFormat) {
+        let notice = Message::Common(ClientMessage::DuplicateConnection);
+        stream.write_all(&format.encode(&notice)).ok();
+        stream.shutdown(Shutdown::Both).ok();
     }
 
     pub fn send_message(&self, msg: Message) -> io::Result<()> {
         match &self.sender {
             Some(sender) => sender
                 .send(ClientEvent::UserInput { message: msg })
-                .map_err(|_| {
-                    io::Error::new(io::ErrorKind::Other, "Error while sending message to peer")
-                }),
+                .map_err(|_| io::Error::other("Error while sending message to peer")),
             None => unreachable!(),
         }
     }
@@ -79,8 +200,148 @@ impl Peer {
 
 impl Drop for Peer {
     fn drop(&mut self) {
+        // El hilo que escribe termina de mandar lo encolado antes de cortar la conexión
         self.sender.take();
-        let _ = self.recv_thread.take().unwrap().join();
         let _ = self.send_thread.take().unwrap().join();
+        // Sin cortar la conexión el hilo que lee seguiría bloqueado esperando al par
+        self.stream.shutdown(Shutdown::Both).ok();
+        let _ = self.recv_thread.take().unwrap().join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::lock::CentralizedLock;
+    use crate::handler::lock_handler::LockProcessor;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::time::Instant;
+
+    fn config(heartbeat_interval: &str, failure_timeout: &str) -> Config {
+        let mut config = Config::default();
+        config
+            .set("heartbeat-interval", heartbeat_interval)
+            .unwrap();
+        config.set("failure-timeout", failure_timeout).unwrap();
+        config
+    }
+
+    /// Dispatcher cuyos eventos para el hilo de pares quedan en el canal
+    fn dispatcher(id: PeerIdType) -> (Dispatcher, Receiver<ClientEvent>) {
+        let (peer_sender, peer_receiver) = channel();
+        let lock = CentralizedLock::new(Duration::from_secs(5));
+        let lock_handler = LockProcessor::new(
+            peer_sender.clone(),
+            Arc::new((Mutex::new(lock), Condvar::new())),
+        );
+        let dispatcher = Dispatcher::new(
+            id,
+            peer_sender,
+            channel().0,
+            channel().0,
+            channel().0,
+            lock_handler,
+        );
+        (dispatcher, peer_receiver)
+    }
+
+    fn connected_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dialed = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        (dialed, accepted)
+    }
+
+    /// Primer par dado por caído antes de `wait`
+    fn disconnection(receiver: &Receiver<ClientEvent>, wait: Duration) -> Option<PeerIdType> {
+        let deadline = Instant::now() + wait;
+        while let Ok(event) =
+            receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if let ClientEvent::PeerDisconnected { peer_id } = event {
+                return Some(peer_id);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn silent_peer_is_suspected() {
+        let (frozen, stream) = connected_pair();
+        let (dispatcher, events) = dispatcher(1);
+        let addr = frozen.local_addr().unwrap();
+        let config = config("100ms", "400ms");
+        let peer = Peer::new(2, addr, stream, true, WireFormat::Text, &config, dispatcher).unwrap();
+        assert_eq!(disconnection(&events, Duration::from_secs(10)), Some(2));
+        assert!(!peer.is_connected());
+        drop(peer);
+        drop(frozen);
+    }
+
+    #[test]
+    fn heartbeats_keep_idle_peers_alive() {
+        let (a_stream, b_stream) = connected_pair();
+        let (a_dispatcher, a_events) = dispatcher(1);
+        let (b_dispatcher, b_events) = dispatcher(2);
+        let addr = a_stream.local_addr().unwrap();
+        let config = config("50ms", "1s");
+        let a = Peer::new(
+            2,
+            addr,
+            a_stream,
+            false,
+            WireFormat::Binary,
+            &config,
+            a_dispatcher,
+        )
+        .unwrap();
+        let b = Peer::new(
+            1,
+            addr,
+            b_stream,
+            true,
+            WireFormat::Binary,
+            &config,
+            b_dispatcher,
+        )
+        .unwrap();
+        // Más que el timeout sin mandar nada más que heartbeats
+        assert_eq!(disconnection(&a_events, Duration::from_millis(2500)), None);
+        assert_eq!(disconnection(&b_events, Duration::ZERO), None);
+        assert!(a.is_connected() && b.is_connected());
+    }
+
+    #[test]
+    fn dropping_a_peer_disconnects_the_other_end() {
+        let (a_stream, b_stream) = connected_pair();
+        let (a_dispatcher, _a_events) = dispatcher(1);
+        let (b_dispatcher, b_events) = dispatcher(2);
+        let addr = a_stream.local_addr().unwrap();
+        // Con un timeout tan largo la caída sólo puede venir del cierre de la conexión
+        let config = config("100ms", "60s");
+        let a = Peer::new(
+            2,
+            addr,
+            a_stream,
+            false,
+            WireFormat::Binary,
+            &config,
+            a_dispatcher,
+        )
+        .unwrap();
+        let b = Peer::new(
+            1,
+            addr,
+            b_stream,
+            true,
+            WireFormat::Binary,
+            &config,
+            b_dispatcher,
+        )
+        .unwrap();
+        drop(a);
+        assert_eq!(disconnection(&b_events, Duration::from_secs(10)), Some(1));
+        assert!(!b.is_connected());
     }
 }
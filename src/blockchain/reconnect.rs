@@ -0,0 +1,116 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::blockchain::peer::PeerIdType;
+
+/// Recuerda la dirección de los pares caídos y de las semillas que no contestaron y calcula
+/// cuánto esperar antes de volver a llamarlos: la espera se duplica con cada intento fallido,
+/// hasta un máximo, y se le resta una parte al azar para que los nodos no reintenten todos a
+/// la vez.
+#[derive(Debug)]
+pub struct ReconnectManager {
+    min_delay: Duration,
+    max_delay: Duration,
+    /// Par caído en cada dirección (ninguno si es una semilla) y espera base del próximo intento
+    pending: HashMap<SocketAddr, (Option<PeerIdType>, Duration)>,
+}
+
+impl ReconnectManager {
+    pub fn new(min_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            min_delay,
+            max_delay,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Anota un par caído; devuelve la espera antes del primer intento
+    pub fn lost(&mut self, peer_id: PeerIdType, addr: SocketAddr) -> Duration {
+        self.pending.insert(addr, (Some(peer_id), self.min_delay));
+        jitter(self.min_delay)
+    }
+
+    /// Anota una semilla, para reintentarla si no contesta aunque todavía no se sepa su id
+    pub fn seed(&mut self, addr: SocketAddr) {
+        self.pending.entry(addr).or_insert((None, self.min_delay));
+    }
+
+    /// Falló un intento. Si la dirección es de un par caído o de una semilla devuelve la
+    /// espera hasta el próximo intento.
+    pub fn failed(&mut self, addr: SocketAddr) -> Option<Duration> {
+        let (_, delay) = self.pending.get_mut(&addr)?;
+        *delay = (*delay * 2).min(self.max_delay).max(self.min_delay);
+        Some(jitter(*delay))
+    }
+
+    /// El par volvió a conectarse, por cualquiera de los dos lados; `dialed` es la dirección a
+    /// la que llamó este nodo, si fue él
+    pub fn reconnected(&mut self, peer_id: PeerIdType, dialed: Option<SocketAddr>) {
+        self.pending
+            .retain(|addr, (pending_id, _)| *pending_id != Some(peer_id) && Some(*addr) != dialed);
+    }
+}
+
+/// Entre la mitad de la espera y la espera completa
+fn jitter(delay: Duration) -> Duration {
+    // Sin dependencias externas: cada `RandomState` nuevo usa claves distintas
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    hasher.write_u128(now.as_nanos());
+    let fraction = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+    delay / 2 + delay.mul_f64(fraction / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 9001));
+        let mut reconnect =
+            ReconnectManager::new(Duration::from_millis(100), Duration::from_millis(500));
+        let within = |delay: Duration, base: u64| {
+            let base = Duration::from_millis(base);
+            assert!(
+                delay >= base / 2 && delay <= base,
+                "{:?} vs {:?}",
+                delay,
+                base
+            );
+        };
+        within(reconnect.lost(2, addr), 100);
+        for base in [200, 400, 500, 500] {
+            within(reconnect.failed(addr).unwrap(), base);
+        }
+
+        reconnect.reconnected(2, None);
+        assert_eq!(reconnect.failed(addr), None);
+        // Un par que vuelve a caerse empieza otra vez con la espera mínima
+        within(reconnect.lost(2, addr), 100);
+    }
+
+    #[test]
+    fn seeds_are_retried_until_reached() {
+        let seed = SocketAddr::from(([127, 0, 0, 1], 9001));
+        let mut reconnect =
+            ReconnectManager::new(Duration::from_millis(100), Duration::from_millis(500));
+        assert_eq!(reconnect.failed(seed), None);
+        reconnect.seed(seed);
+        assert!(reconnect.failed(seed).is_some());
+        assert!(reconnect.failed(seed).is_some());
+        reconnect.reconnected(3, Some(seed));
+        assert_eq!(reconnect.failed(seed), None);
+    }
+
+    #[test]
+    fn jitter_spreads_retries() {
+        let delays: Vec<_> = (0..20).map(|_| jitter(Duration::from_secs(1))).collect();
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+}
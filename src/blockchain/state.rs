@@ -0,0 +1,191 @@
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::blockchain::blockchain::{GradeKey, Transaction};
+
+/// Notas vigentes por (alumno, materia, evaluación). La cadena la actualiza a medida que se
+/// agregan bloques, por lo que las consultas no necesitan recorrer los bloques.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GradeState {
+    grades: BTreeMap<GradeKey, u16>,
+}
+
+impl GradeState {
+    pub fn new() -> Self {
+        Self {
+            grades: BTreeMap::new(),
+        }
+    }
+
+    pub fn apply(&mut self, transaction: &Transaction) {
+        match transaction {
+            Transaction::Insert(data) | Transaction::Update { data, .. } => {
+                self.grades.insert(data.key().clone(), data.score());
+            }
+            Transaction::Remove(key) => {
+                self.grades.remove(key);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &GradeKey) -> Option<u16> {
+        self.grades.get(key).copied()
+    }
+
+    pub fn contains(&self, key: &GradeKey) -> bool {
+        self.grades.contains_key(key)
+    }
+
+    /// Notas de un alumno ordenadas por materia y evaluación
+    pub fn grades_of<'a>(&'a self, student: &'a str) -> impl Iterator<Item = (&'a GradeKey, u16)> {
+        self.grades
+            .range(GradeKey::new(student, "", "")..)
+            .take_while(move |(key, _)| key.student() == student)
+            .map(|(key, score)| (key, *score))
+    }
+
+    /// Alumnos con al menos una nota, ordenados alfabéticamente
+    pub fn students(&self) -> Vec<&str> {
+        let mut students: Vec<&str> = self.grades.keys().map(GradeKey::student).collect();
+        students.dedup();
+        students
+    }
+
+    /// Cantidad de notas vigentes
+    pub fn len(&self) -> usize {
+        self.grades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grades.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.grades.iter(),
+        }
+    }
+}
+
+impl fmt::Display for GradeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, score) in self.iter() {
+            writeln!(f, "Student {} -> {}", key, score)?;
+        }
+        Ok(())
+    }
+}
+
+/// Una transacción de la cadena junto con el bloque que la contiene
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradeChange {
+    height: usize,
+    timestamp: u64,
+    transaction: Transaction,
+}
+
+impl GradeChange {
+    pub fn new(height: usize, timestamp: u64, transaction: Transaction) -> Self {
+        Self {
+            height,
+            timestamp,
+            transaction,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Milisegundos desde epoch del bloque que contiene el cambio
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+}
+
+impl fmt::Display for GradeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Block {}: {}", self.height, self.transaction)
+    }
+}
+
+/// Recorre las notas ordenadas por alumno, materia y evaluación
+pub struct Iter<'a> {
+    inner: btree_map::Iter<'a, GradeKey, u16>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a GradeKey, u16);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, score)| (key, *score))
+    }
+}
+
+impl<'a> IntoIterator for &'a GradeState {
+    type Item = (&'a GradeKey, u16);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::blockchain::TransactionData;
+
+    fn insert(student: &str, evaluation: &str, score: u16) -> Transaction {
+        Transaction::Insert(TransactionData::new(student, "algo1", evaluation, score))
+    }
+
+    #[test]
+    fn applies_inserts_updates_and_removes() {
+        let mut state = GradeState::new();
+        state.apply(&insert("pedro", "parcial", 7));
+        state.apply(&insert("juan", "parcial", 4));
+        state.apply(&Transaction::Update {
+            data: TransactionData::new("pedro", "algo1", "parcial", 9),
+            previous: 7,
+        });
+        state.apply(&Transaction::Remove(GradeKey::new(
+            "juan", "algo1", "parcial",
+        )));
+        assert_eq!(
+            state.get(&GradeKey::new("pedro", "algo1", "parcial")),
+            Some(9)
+        );
+        assert_eq!(state.get(&GradeKey::new("juan", "algo1", "parcial")), None);
+        assert_eq!(state.len(), 1);
+    }
+
+    #[test]
+    fn students_are_sorted_and_unique() {
+        let mut state = GradeState::new();
+        state.apply(&insert("pedro", "parcial", 7));
+        state.apply(&insert("ana", "parcial", 4));
+        state.apply(&insert("ana", "final", 6));
+        state.apply(&insert("juan", "parcial", 9));
+        assert_eq!(state.students(), vec!["ana", "juan", "pedro"]);
+        assert_eq!(state.len(), 4);
+    }
+
+    #[test]
+    fn grades_of_a_student() {
+        let mut state = GradeState::new();
+        state.apply(&insert("ana", "parcial", 4));
+        state.apply(&insert("anabel", "parcial", 8));
+        state.apply(&insert("ana", "final", 6));
+        let grades: Vec<(&str, u16)> = state
+            .grades_of("ana")
+            .map(|(key, score)| (key.evaluation(), score))
+            .collect();
+        assert_eq!(grades, vec![("final", 6), ("parcial", 4)]);
+    }
+}
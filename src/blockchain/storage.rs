@@ -0,0 +1,398 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::blockchain::blockchain::{Block, Blockchain, TermType};
+use crate::blockchain::hash::{BlockHash, Sha256, HASH_SIZE};
+use crate::communication::tokenizer::tokenize;
+
+const CHAIN_FILE: &str = "chain.log";
+const CHAIN_TMP_FILE: &str = "chain.log.tmp";
+const TERM_FILE: &str = "term";
+const TERM_TMP_FILE: &str = "term.tmp";
+const LENGTH_SIZE: usize = 4;
+const RECORD_HEADER_SIZE: usize = LENGTH_SIZE + HASH_SIZE;
+
+/// Archivo append-only con un registro por bloque: largo del payload (u32 big endian),
+/// SHA-256 del payload y el bloque serializado.
+#[derive(Debug)]
+pub struct ChainStore {
+    dir: PathBuf,
+    file: File,
+    height: usize,
+    tip: BlockHash,
+}
+
+impl ChainStore {
+    /// Abre (o crea) la cadena guardada en `data_dir`. Si el último registro quedó a medio
+    /// escribir por una caída se trunca; cualquier otra corrupción es un error.
+    pub fn open(data_dir: &Path) -> io::Result<(Self, Blockchain)> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(CHAIN_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let (blockchain, valid_len) = ChainStore::load(&file)?;
+        if valid_len < file.metadata()?.len() {
+            warn!(
+                "Truncating torn record at offset {} of {}",
+                valid_len,
+                path.display()
+            );
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        blockchain
+            .verify()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let store = ChainStore {
+            dir: data_dir.to_path_buf(),
+            file,
+            height: blockchain.blocks().len(),
+            tip: ChainStore::tip_of(&blockchain),
+        };
+        Ok((store, blockchain))
+    }
+
+    /// Persiste `blockchain`: si extiende lo guardado sólo se agregan los bloques nuevos,
+    /// si no se reescribe el archivo completo.
+    pub fn sync(&mut self, blockchain: &Blockchain) -> io::Result<()> {
+        let blocks = blockchain.blocks();
+        let extends = self.height <= blocks.len()
+            && (self.height == 0 || *blocks[self.height - 1].hash() == self.tip);
+        if !extends {
+            return self.rewrite(blockchain);
+        }
+        if self.height == blocks.len() {
+            return Ok(());
+        }
+        for block in &blocks[self.height..] {
+            self.file.write_all(&ChainStore::encode(block))?;
+        }
+        self.file.sync_data()?;
+        self.height = blocks.len();
+        self.tip = ChainStore::tip_of(blockchain);
+        Ok(())
+    }
+
+    fn rewrite(&mut self, blockchain: &Blockchain) -> io::Result<()> {
+        let tmp_path = self.dir.join(CHAIN_TMP_FILE);
+        let path = self.dir.join(CHAIN_FILE);
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for block in blockchain.blocks() {
+                tmp.write_all(&ChainStore::encode(block))?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        File::open(&self.dir)?.sync_all()?;
+        self.file = OpenOptions::new().read(true).append(true).open(&path)?;
+        self.height = blockchain.blocks().len();
+        self.tip = ChainStore::tip_of(blockchain);
+        Ok(())
+    }
+
+    fn tip_of(blockchain: &Blockchain) -> BlockHash {
+        match blockchain.get_last() {
+            Some(block) => *block.hash(),
+            None => BlockHash::zero(),
+        }
+    }
+
+    fn encode(block: &Block) -> Vec<u8> {
+        let payload = block.serialize();
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        record.extend_from_slice(Sha256::digest(payload.as_bytes()).as_bytes());
+        record.extend_from_slice(payload.as_bytes());
+        record
+    }
+
+    /// Devuelve la cadena leída y cuántos bytes del archivo contienen registros completos.
+    /// Lee el archivo de una pasada sin cargarlo entero: los registros van enmarcados por su
+    /// largo y sólo el último puede estar dañado sin que sea corrupción.
+    fn load(file: &File) -> io::Result<(Blockchain, u64)> {
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut blockchain = Blockchain::new();
+        let mut offset = 0;
+        let mut header = [0; RECORD_HEADER_SIZE];
+        while file_len - offset >= RECORD_HEADER_SIZE as u64 {
+            reader.read_exact(&mut header)?;
+            let mut length = [0; LENGTH_SIZE];
+            length.copy_from_slice(&header[..LENGTH_SIZE]);
+            let length = u32::from_be_bytes(length) as u64;
+            let end = offset + RECORD_HEADER_SIZE as u64 + length;
+            if end > file_len {
+                // Lo que llegó a escribirse de un registro cortado es parte de su payload; si
+                // aparece el encabezado binario de otro registro lo dañado es el largo
+                let mut rest = Vec::new();
+                reader.read_to_end(&mut rest)?;
+                if !ChainStore::is_payload_prefix(&rest) {
+                    return Err(ChainStore::corrupted(blockchain.blocks().len()));
+                }
+                break;
+            }
+            let mut payload = vec![0; length as usize];
+            reader.read_exact(&mut payload)?;
+            if Sha256::digest(&payload).as_bytes() != &header[LENGTH_SIZE..] {
+                if end == file_len {
+                    break;
+                }
+                return Err(ChainStore::corrupted(blockchain.blocks().len()));
+            }
+            let block = std::str::from_utf8(&payload)
+                .ok()
+                .and_then(|payload| tokenize(payload).ok())
+                .and_then(|tokens| {
+                    let mut tokens = tokens.iter().map(String::as_str);
+                    match tokens.next() {
+                        Some("block") => Block::parse(&mut tokens).ok(),
+                        _ => None,
+                    }
+                })
+                .ok_or_else(|| ChainStore::corrupted(blockchain.blocks().len()))?;
+            blockchain.add_block(block);
+            offset = end;
+        }
+        Ok((blockchain, offset))
+    }
+
+    /// Si `bytes` puede ser el comienzo de un bloque serializado: texto sin el cero con el
+    /// que empieza el largo de cualquier registro siguiente
+    fn is_payload_prefix(bytes: &[u8]) -> bool {
+        let text_len = match std::str::from_utf8(bytes) {
+            Ok(_) => bytes.len(),
+            // El corte pudo caer en medio de un carácter
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => return false,
+        };
+        !bytes[..text_len].contains(&0)
+    }
+
+    fn corrupted(height: usize) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Corrupted record for block {} in chain file", height),
+        )
+    }
+}
+
+/// Última época de elección vista. Se guarda para que un nodo reiniciado no acepte ni
+/// convoque elecciones de épocas que ya pasaron.
+#[derive(Debug)]
+pub struct TermStore {
+    dir: PathBuf,
+}
+
+impl TermStore {
+    /// Abre el archivo de época de `data_dir`; si no existe la época es 0
+    pub fn open(data_dir: &Path) -> io::Result<(Self, TermType)> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(TERM_FILE);
+        let term = match fs::read_to_string(&path) {
+            Ok(contents) => contents.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid term in {}", path.display()),
+                )
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let store = TermStore {
+            dir: data_dir.to_path_buf(),
+        };
+        Ok((store, term))
+    }
+
+    /// Reemplaza la época guardada escribiendo un archivo temporal y renombrándolo
+    pub fn save(&self, term: TermType) -> io::Result<()> {
+        let tmp_path = self.dir.join(TERM_TMP_FILE);
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            writeln!(tmp, "{}", term)?;
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, self.dir.join(TERM_FILE))?;
+        File::open(&self.dir)?.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::blockchain::{Transaction, TransactionData};
+    use std::io::{Seek, SeekFrom};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chain_store_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn insert(student: &str, score: u16) -> Vec<Transaction> {
+        vec![Transaction::Insert(TransactionData::new(
+            student, "algo1", "parcial", score,
+        ))]
+    }
+
+    #[test]
+    fn chain_is_reloaded_after_restart() {
+        let dir = temp_dir("reload");
+        let (mut store, mut blockchain) = ChainStore::open(&dir).unwrap();
+        assert_eq!(blockchain.height(), 0);
+        blockchain.add_transactions(insert("pedro", 7), 1, 1);
+        store.sync(&blockchain).unwrap();
+        blockchain.add_transactions(insert("Juan Pablo", 4), 1, 1);
+        store.sync(&blockchain).unwrap();
+        drop(store);
+
+        let (_, reloaded) = ChainStore::open(&dir).unwrap();
+        assert_eq!(reloaded.blocks(), blockchain.blocks());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn torn_final_record_is_truncated() {
+        let dir = temp_dir("torn");
+        let (mut store, mut blockchain) = ChainStore::open(&dir).unwrap();
+        blockchain.add_transactions(insert("pedro", 7), 1, 1);
+        store.sync(&blockchain).unwrap();
+        let committed_len = fs::metadata(dir.join(CHAIN_FILE)).unwrap().len();
+        blockchain.add_transactions(insert("juan", 4), 1, 1);
+        store.sync(&blockchain).unwrap();
+        drop(store);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .open(dir.join(CHAIN_FILE))
+            .unwrap();
+        let full_len = file.metadata().unwrap().len();
+        file.set_len(full_len - 10).unwrap();
+
+        let (_, reloaded) = ChainStore::open(&dir).unwrap();
+        assert_eq!(reloaded.height(), 1);
+        assert_eq!(
+            fs::metadata(dir.join(CHAIN_FILE)).unwrap().len(),
+            committed_len
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_final_record_is_truncated() {
+        let dir = temp_dir("damaged_tail");
+        let (mut store, mut blockchain) = ChainStore::open(&dir).unwrap();
+        blockchain.add_transactions(insert("pedro", 7), 1, 1);
+        store.sync(&blockchain).unwrap();
+        let committed_len = fs::metadata(dir.join(CHAIN_FILE)).unwrap().len();
+        blockchain.add_transactions(insert("juan", 4), 1, 1);
+        store.sync(&blockchain).unwrap();
+        drop(store);
+
+        // El último registro termina justo en el final del archivo pero no pasa el checksum
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(dir.join(CHAIN_FILE))
+            .unwrap();
+        file.seek(SeekFrom::End(-1)).unwrap();
+        file.write_all(b"x").unwrap();
+        drop(file);
+
+        let (_, reloaded) = ChainStore::open(&dir).unwrap();
+        assert_eq!(reloaded.height(), 1);
+        assert_eq!(
+            fs::metadata(dir.join(CHAIN_FILE)).unwrap().len(),
+            committed_len
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_record_in_the_middle_is_an_error() {
+        let dir = temp_dir("corrupted");
+        let (mut store, mut blockchain) = ChainStore::open(&dir).unwrap();
+        blockchain.add_transactions(insert("pedro", 7), 1, 1);
+        blockchain.add_transactions(insert("juan", 4), 1, 1);
+        store.sync(&blockchain).unwrap();
+        drop(store);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(dir.join(CHAIN_FILE))
+            .unwrap();
+        file.seek(SeekFrom::Start(RECORD_HEADER_SIZE as u64 + 8))
+            .unwrap();
+        file.write_all(b"x").unwrap();
+        drop(file);
+
+        let err = ChainStore::open(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_length_in_the_middle_is_an_error() {
+        let dir = temp_dir("corrupted_length");
+        let (mut store, mut blockchain) = ChainStore::open(&dir).unwrap();
+        blockchain.add_transactions(insert("pedro", 7), 1, 1);
+        blockchain.add_transactions(insert("juan", 4), 1, 1);
+        blockchain.add_transactions(insert("ana", 9), 1, 1);
+        store.sync(&blockchain).unwrap();
+        drop(store);
+        let path = dir.join(CHAIN_FILE);
+        let full_len = fs::metadata(&path).unwrap().len();
+
+        // El largo del primer registro pasa a apuntar más allá del final del archivo
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all(&(full_len as u32).to_be_bytes()).unwrap();
+        drop(file);
+
+        let err = ChainStore::open(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::metadata(&path).unwrap().len(), full_len);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diverging_chain_rewrites_the_file() {
+        let dir = temp_dir("rewrite");
+        let (mut store, mut blockchain) = ChainStore::open(&dir).unwrap();
+        blockchain.add_transactions(insert("pedro", 7), 1, 1);
+        blockchain.add_transactions(insert("juan", 4), 1, 1);
+        store.sync(&blockchain).unwrap();
+
+        let mut other = Blockchain::new();
+        other.add_transactions(insert("ana", 9), 2, 2);
+        store.sync(&other).unwrap();
+        other.add_transactions(insert("luis", 3), 2, 2);
+        store.sync(&other).unwrap();
+        drop(store);
+
+        let (_, reloaded) = ChainStore::open(&dir).unwrap();
+        assert_eq!(reloaded.blocks(), other.blocks());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn term_survives_restart() {
+        let dir = temp_dir("term");
+        let (store, term) = TermStore::open(&dir).unwrap();
+        assert_eq!(term, 0);
+        store.save(3).unwrap();
+        store.save(12).unwrap();
+        assert_eq!(TermStore::open(&dir).unwrap().1, 12);
+
+        fs::write(dir.join(TERM_FILE), "doce\n").unwrap();
+        assert_eq!(
+            TermStore::open(&dir).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
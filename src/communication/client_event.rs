@@ -1,10 +1,16 @@
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
 use std::sync::mpsc::Sender;
-use std::time::SystemTime;
 
-use crate::blockchain::blockchain::{Blockchain, Transaction};
+use crate::blockchain::blockchain::{Block, Blockchain, TermType, Transaction, ValidationError};
+use crate::blockchain::hash::BlockHash;
+use crate::blockchain::parse_error::{next_field, parse_field, FieldError};
 use crate::blockchain::peer::PeerIdType;
-use crate::communication::serialization::Serializable;
+use crate::communication::handshake::Handshake;
+use crate::communication::serialization::{
+    next_action, put_str, put_u32, put_u64, put_u8, BinarySerializable, ByteReader,
+    MessageParseError, Serializable,
+};
+use crate::communication::tokenizer::{quote, tokenize};
 
 #[derive(Debug)]
 pub enum ClientEvent {
@@ -12,6 +18,12 @@ pub enum ClientEvent {
         stream: TcpStream,
         incoming: bool,
     },
+    /// Conexión que ya completó el handshake
+    PeerConnected {
+        stream: TcpStream,
+        handshake: Handshake,
+        incoming: bool,
+    },
     PeerMessage {
         message: Message,
         peer_id: PeerIdType,
@@ -22,18 +34,16 @@ pub enum ClientEvent {
     UserInput {
         message: Message,
     },
-}
-impl ClientEvent {
-    pub fn serialize(&self) -> String {
-        match self {
-            ClientEvent::UserInput { message } => message.serialize(),
-            ClientEvent::PeerMessage {
-                message,
-                peer_id: _,
-            } => message.serialize(),
-            _ => unreachable!(),
-        }
-    }
+    /// Nodos que conoce un par, para conectarse a los que falten
+    KnownPeers {
+        peers: Vec<(PeerIdType, SocketAddr)>,
+    },
+    /// No se pudo conectar a un nodo anunciado
+    DialFailed {
+        addr: SocketAddr,
+    },
+    /// Cierra las conexiones con los pares y termina el hilo de pares
+    Shutdown,
 }
 
 #[derive(Clone, Debug)]
@@ -52,185 +62,1075 @@ impl Serializable for Message {
         }
     }
 
-    fn deserialize(line: &str) -> Option<Self> {
-        if let Some(message) = ClientMessage::deserialize(line) {
-            Some(Message::Common(message))
-        } else if let Some(message) = LeaderMessage::deserialize(line) {
-            Some(Message::Leader(message))
-        } else {
-            let message = LockMessage::deserialize(line)?;
-            Some(Message::Lock(message))
+    /// Tokeniza la línea una sola vez y elige la familia de mensajes por la acción
+    fn deserialize(line: &str) -> Result<Self, MessageParseError> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.iter().map(String::as_str);
+        match next_action(&mut tokens)? {
+            action @ ("le" | "coordinator" | "ok") => {
+                LeaderMessage::parse(action, &mut tokens).map(Message::Leader)
+            }
+            action @ ("lock_acquire" | "lock_release") => {
+                LockMessage::parse(action, &mut tokens).map(Message::Lock)
+            }
+            action => ClientMessage::parse(action, &mut tokens).map(Message::Common),
         }
     }
 }
 
+/// Identifica un pedido del usuario; el líder lo repite en la respuesta para que el nodo
+/// que pidió la asocie al comando correcto
+pub type RequestId = u64;
+
 #[derive(Clone, Debug)]
 pub enum ClientMessage {
-    ReadBlockchainRequest,
-    ReadBlockchainResponse { blockchain: Blockchain },
-    WriteBlockchainRequest { transaction: Transaction },
-    WriteBlockchainResponse { transaction: Transaction },
-    LockResponse(bool),
+    ReadBlockchainRequest {
+        request_id: RequestId,
+    },
+    ReadBlockchainResponse {
+        request_id: RequestId,
+        blockchain: Blockchain,
+    },
+    WriteBlockchainRequest {
+        request_id: RequestId,
+        transactions: Vec<Transaction>,
+    },
+    /// Lleva la época del líder que commiteó, para descartar respuestas de un líder viejo
+    WriteBlockchainResponse {
+        request_id: RequestId,
+        term: TermType,
+        transactions: Vec<Transaction>,
+    },
+    LockResponse {
+        request_id: RequestId,
+        acquired: bool,
+    },
     LeaderElectionFinished,
-    ErrorResponse(ErrorMessage),
-    BroadcastBlockchain { blockchain: Blockchain },
+    /// Los errores que no responden a un pedido, como un mensaje ilegible, van sin id
+    ErrorResponse {
+        request_id: Option<RequestId>,
+        error: ErrorMessage,
+    },
+    AppendBlock {
+        block: Block,
+    },
+    BlocksRequest {
+        from: usize,
+    },
+    BlocksResponse {
+        from: usize,
+        height: usize,
+        blocks: Vec<Block>,
+    },
+    SyncStatusRequest,
+    SyncStatus {
+        height: usize,
+        tip: BlockHash,
+    },
+    /// Id y dirección en la que escuchan los demás pares del emisor
+    KnownPeers {
+        peers: Vec<(PeerIdType, SocketAddr)>,
+    },
+    /// Se manda cuando la conexión está ociosa, para que el otro extremo sepa que el nodo
+    /// sigue vivo
+    Heartbeat,
+    /// Se cierra una conexión repetida; el par sigue conectado por la otra
+    DuplicateConnection,
 }
 
 #[derive(Clone, Debug)]
 pub enum ErrorMessage {
     NotLeaderError,
     LockNotAcquiredError,
+    InvalidBlockchainError,
+    RejectedTransactionError(ValidationError),
+    MalformedMessageError(String),
 }
 
 impl Serializable for ClientMessage {
     fn serialize(&self) -> String {
         match self {
-            ClientMessage::ReadBlockchainRequest {} => "rb\n".to_owned(),
-            ClientMessage::ReadBlockchainResponse { blockchain } => {
-                format!("blockchain {}\n", blockchain.serialize())
+            ClientMessage::ReadBlockchainRequest { request_id } => format!("rb {}\n", request_id),
+            ClientMessage::ReadBlockchainResponse {
+                request_id,
+                blockchain,
+            } => {
+                format!("blockchain {} {}\n", request_id, blockchain.serialize())
             }
-            ClientMessage::WriteBlockchainRequest { transaction } => {
-                format!("wb {}\n", transaction.serialize())
+            ClientMessage::WriteBlockchainRequest {
+                request_id,
+                transactions,
+            } => {
+                format!(
+                    "wb {} {}\n",
+                    request_id,
+                    Transaction::serialize_all(transactions)
+                )
             }
-            ClientMessage::WriteBlockchainResponse { transaction } => {
-                format!("wb_response {}\n", transaction.serialize())
+            ClientMessage::WriteBlockchainResponse {
+                request_id,
+                term,
+                transactions,
+            } => {
+                format!(
+                    "wb_response {} {} {}\n",
+                    request_id,
+                    term,
+                    Transaction::serialize_all(transactions)
+                )
             }
-            ClientMessage::LockResponse(acquired) => {
+            ClientMessage::LockResponse {
+                request_id,
+                acquired,
+            } => {
                 if *acquired {
-                    "lock_ok\n".to_owned()
+                    format!("lock_ok {}\n", request_id)
                 } else {
-                    "lock_failed\n".to_owned()
+                    format!("lock_failed {}\n", request_id)
                 }
             }
-            ClientMessage::ErrorResponse(ErrorMessage::NotLeaderError) => {
-                "error not_leader\n".to_owned()
+            ClientMessage::ErrorResponse { request_id, error } => {
+                let request_id = request_id.map_or("-".to_owned(), |id| id.to_string());
+                format!("error {} {}\n", request_id, error.serialize())
             }
-            ClientMessage::ErrorResponse(ErrorMessage::LockNotAcquiredError) => {
-                "error not_locked\n".to_owned()
+            ClientMessage::LeaderElectionFinished => "info leader_election_finished\n".to_owned(),
+            ClientMessage::AppendBlock { block } => format!("append_block {}\n", block.serialize()),
+            ClientMessage::BlocksRequest { from } => format!("blocks_request {}\n", from),
+            ClientMessage::BlocksResponse {
+                from,
+                height,
+                blocks,
+            } => {
+                let mut response = format!("blocks {} {} {}", from, height, blocks.len());
+                for block in blocks {
+                    response = format!("{} {}", response, block.serialize());
+                }
+                format!("{}\n", response)
             }
-            ClientMessage::LeaderElectionFinished {} => {
-                "info leader_election_finished\n".to_owned()
+            ClientMessage::SyncStatusRequest => "sync_status_request\n".to_owned(),
+            ClientMessage::SyncStatus { height, tip } => {
+                format!("sync_status {} {}\n", height, tip)
             }
-            ClientMessage::BroadcastBlockchain { blockchain } => {
-                format!("blockchain {}\n", blockchain.serialize())
+            ClientMessage::KnownPeers { peers } => {
+                let mut message = "peers".to_owned();
+                for (id, addr) in peers {
+                    message = format!("{} {} {}", message, id, addr);
+                }
+                format!("{}\n", message)
             }
+            ClientMessage::Heartbeat => "heartbeat\n".to_owned(),
+            ClientMessage::DuplicateConnection => "duplicate_connection\n".to_owned(),
         }
     }
 
-    fn deserialize(line: &str) -> Option<ClientMessage> {
-        let mut tokens = line.split_whitespace();
-        let action = tokens.next();
-        match action {
-            Some("rb") => Some(ClientMessage::ReadBlockchainRequest {}),
-            Some("wb") => ClientMessage::parse_write_blockchain(&mut tokens),
-            Some("wb_response") => ClientMessage::parse_write_response(&mut tokens),
-            Some("lock_failed") => Some(ClientMessage::LockResponse(false)),
-            Some("lock_ok") => Some(ClientMessage::LockResponse(true)),
-            Some("blockchain") => ClientMessage::parse_blockchain(&mut tokens),
-            Some("error") => ClientMessage::parse_error(&mut tokens),
-            _ => None,
-        }
+    fn deserialize(line: &str) -> Result<ClientMessage, MessageParseError> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.iter().map(String::as_str);
+        let action = next_action(&mut tokens)?;
+        ClientMessage::parse(action, &mut tokens)
     }
 }
 
 impl ClientMessage {
-    fn parse_write_blockchain(tokens: &mut dyn Iterator<Item = &str>) -> Option<ClientMessage> {
-        let transaction = Transaction::parse(tokens)?;
-        Some(ClientMessage::WriteBlockchainRequest { transaction })
+    /// Arma el mensaje de `action` con el resto de los tokens de la línea
+    fn parse(
+        action: &str,
+        mut tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        match action {
+            "rb" => Ok(ClientMessage::ReadBlockchainRequest {
+                request_id: parse_field(&mut tokens, "request id")?,
+            }),
+            "wb" => ClientMessage::parse_write_blockchain(&mut tokens),
+            "wb_response" => ClientMessage::parse_write_response(&mut tokens),
+            "lock_failed" => Ok(ClientMessage::LockResponse {
+                request_id: parse_field(&mut tokens, "request id")?,
+                acquired: false,
+            }),
+            "lock_ok" => Ok(ClientMessage::LockResponse {
+                request_id: parse_field(&mut tokens, "request id")?,
+                acquired: true,
+            }),
+            "blockchain" => ClientMessage::parse_blockchain(&mut tokens),
+            "error" => ClientMessage::parse_error(&mut tokens),
+            "append_block" => Ok(ClientMessage::AppendBlock {
+                block: ClientMessage::parse_block(&mut tokens)?,
+            }),
+            "blocks_request" => Ok(ClientMessage::BlocksRequest {
+                from: parse_field(&mut tokens, "from height")?,
+            }),
+            "blocks" => ClientMessage::parse_blocks(&mut tokens),
+            "sync_status_request" => Ok(ClientMessage::SyncStatusRequest),
+            "sync_status" => ClientMessage::parse_sync_status(&mut tokens),
+            "peers" => ClientMessage::parse_known_peers(&mut tokens),
+            "heartbeat" => Ok(ClientMessage::Heartbeat),
+            "duplicate_connection" => Ok(ClientMessage::DuplicateConnection),
+            other => Err(MessageParseError::UnknownMessage(other.to_owned())),
+        }
+    }
+
+    fn parse_write_blockchain(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        let request_id = parse_field(tokens, "request id")?;
+        let transactions = Transaction::parse_all(tokens)?;
+        Ok(ClientMessage::WriteBlockchainRequest {
+            request_id,
+            transactions,
+        })
     }
 
-    fn parse_write_response(tokens: &mut dyn Iterator<Item = &str>) -> Option<ClientMessage> {
-        let transaction = Transaction::parse(tokens)?;
-        Some(ClientMessage::WriteBlockchainResponse { transaction })
+    fn parse_write_response(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        let request_id = parse_field(tokens, "request id")?;
+        let term = parse_field(tokens, "term")?;
+        let transactions = Transaction::parse_all(tokens)?;
+        Ok(ClientMessage::WriteBlockchainResponse {
+            request_id,
+            term,
+            transactions,
+        })
     }
 
-    fn parse_blockchain(tokens: &mut dyn Iterator<Item = &str>) -> Option<ClientMessage> {
-        Some(ClientMessage::ReadBlockchainResponse {
+    fn parse_blockchain(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        Ok(ClientMessage::ReadBlockchainResponse {
+            request_id: parse_field(tokens, "request id")?,
             blockchain: Blockchain::parse(tokens)?,
         })
     }
 
-    fn parse_error(tokens: &mut dyn Iterator<Item = &str>) -> Option<ClientMessage> {
-        let error_str = tokens.next()?;
-        match error_str {
-            "not_leader" => Some(ClientMessage::ErrorResponse(ErrorMessage::NotLeaderError)),
-            "not_locked" => Some(ClientMessage::ErrorResponse(
-                ErrorMessage::LockNotAcquiredError,
-            )),
+    fn parse_block(tokens: &mut dyn Iterator<Item = &str>) -> Result<Block, MessageParseError> {
+        match next_field(tokens, "block")? {
+            "block" => Ok(Block::parse(tokens)?),
+            other => Err(MessageParseError::Field(FieldError::Invalid {
+                field: "block",
+                value: other.to_owned(),
+            })),
+        }
+    }
+
+    fn parse_blocks(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        let from = parse_field(tokens, "from height")?;
+        let height = parse_field(tokens, "height")?;
+        let count: usize = parse_field(tokens, "block count")?;
+        let mut blocks = Vec::new();
+        for _ in 0..count {
+            blocks.push(ClientMessage::parse_block(tokens)?);
+        }
+        Ok(ClientMessage::BlocksResponse {
+            from,
+            height,
+            blocks,
+        })
+    }
+
+    fn parse_sync_status(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        let height = parse_field(tokens, "height")?;
+        let tip = next_field(tokens, "tip")?;
+        let tip = BlockHash::parse(tip).ok_or_else(|| FieldError::Invalid {
+            field: "tip",
+            value: tip.to_owned(),
+        })?;
+        Ok(ClientMessage::SyncStatus { height, tip })
+    }
+
+    fn parse_known_peers(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        let mut tokens = tokens.peekable();
+        let mut peers = Vec::new();
+        while tokens.peek().is_some() {
+            let id = parse_field(&mut tokens, "peer id")?;
+            let addr = parse_field(&mut tokens, "peer address")?;
+            peers.push((id, addr));
+        }
+        Ok(ClientMessage::KnownPeers { peers })
+    }
+
+    fn parse_error(
+        tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<ClientMessage, MessageParseError> {
+        let request_id = match next_field(tokens, "request id")? {
+            "-" => None,
+            id => Some(id.parse().map_err(|_| FieldError::Invalid {
+                field: "request id",
+                value: id.to_owned(),
+            })?),
+        };
+        let error = match next_field(tokens, "error kind")? {
+            "not_leader" => ErrorMessage::NotLeaderError,
+            "not_locked" => ErrorMessage::LockNotAcquiredError,
+            "invalid_blockchain" => ErrorMessage::InvalidBlockchainError,
+            "rejected" => ErrorMessage::RejectedTransactionError(ValidationError::parse(tokens)?),
+            "malformed" => {
+                ErrorMessage::MalformedMessageError(next_field(tokens, "reason")?.to_owned())
+            }
+            other => {
+                return Err(MessageParseError::Field(FieldError::Invalid {
+                    field: "error kind",
+                    value: other.to_owned(),
+                }))
+            }
+        };
+        Ok(ClientMessage::ErrorResponse { request_id, error })
+    }
+
+    /// Id del pedido al que responde el mensaje, si es una respuesta
+    pub fn request_id(&self) -> Option<RequestId> {
+        match self {
+            ClientMessage::ReadBlockchainResponse { request_id, .. }
+            | ClientMessage::WriteBlockchainResponse { request_id, .. }
+            | ClientMessage::LockResponse { request_id, .. } => Some(*request_id),
+            ClientMessage::ErrorResponse { request_id, .. } => *request_id,
             _ => None,
         }
     }
 }
 
+impl ErrorMessage {
+    fn serialize(&self) -> String {
+        match self {
+            ErrorMessage::NotLeaderError => "not_leader".to_owned(),
+            ErrorMessage::LockNotAcquiredError => "not_locked".to_owned(),
+            ErrorMessage::InvalidBlockchainError => "invalid_blockchain".to_owned(),
+            ErrorMessage::RejectedTransactionError(error) => {
+                format!("rejected {}", error.serialize())
+            }
+            ErrorMessage::MalformedMessageError(reason) => format!("malformed {}", quote(reason)),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
+/// Los mensajes de la elección llevan la época de quien los manda; los de una época vieja
+/// se descartan
 pub enum LeaderMessage {
-    LeaderElectionRequest { timestamp: SystemTime },
+    LeaderElectionRequest { term: TermType },
     CurrentLeaderLocal { response_sender: Sender<PeerIdType> },
-    OkMessage,
-    VictoryMessage,
+    CurrentTermLocal { response_sender: Sender<TermType> },
+    SeenTermLocal { response_sender: Sender<TermType> },
+    OkMessage { term: TermType },
+    VictoryMessage { term: TermType },
     PeerDisconnected,
     SendWelcome,
-    BroadcastBlockchain { blockchain: Blockchain },
+    BroadcastBlock { block: Block },
 }
 
 impl LeaderMessage {
     pub fn serialize(&self) -> String {
         match self {
-            LeaderMessage::LeaderElectionRequest { timestamp } => {
-                let time_epoch = timestamp.duration_since(std::time::UNIX_EPOCH).unwrap();
-                format!("le {}\n", time_epoch.as_secs())
-            }
+            LeaderMessage::LeaderElectionRequest { term } => format!("le {}\n", term),
             LeaderMessage::CurrentLeaderLocal { .. } => {
                 unreachable!()
             }
-            LeaderMessage::OkMessage {} => "ok\n".to_owned(),
-            LeaderMessage::VictoryMessage {} => "coordinator\n".to_owned(),
+            LeaderMessage::CurrentTermLocal { .. } => unreachable!(),
+            LeaderMessage::SeenTermLocal { .. } => unreachable!(),
+            LeaderMessage::OkMessage { term } => format!("ok {}\n", term),
+            LeaderMessage::VictoryMessage { term } => format!("coordinator {}\n", term),
             LeaderMessage::PeerDisconnected => unreachable!(),
             LeaderMessage::SendWelcome => unreachable!(),
-            LeaderMessage::BroadcastBlockchain { blockchain: _ } => unreachable!(),
+            LeaderMessage::BroadcastBlock { .. } => unreachable!(),
         }
     }
 
-    pub fn deserialize(line: &str) -> Option<LeaderMessage> {
-        let mut tokens = line.split_whitespace();
-        let action = tokens.next();
-        match action {
-            Some("le") => LeaderMessage::parse_leader_req(&mut tokens),
-            Some("coordinator") => Some(LeaderMessage::VictoryMessage {}),
-            Some("ok") => Some(LeaderMessage::OkMessage {}),
-            _ => None,
-        }
+    pub fn deserialize(line: &str) -> Result<LeaderMessage, MessageParseError> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.iter().map(String::as_str);
+        let action = next_action(&mut tokens)?;
+        LeaderMessage::parse(action, &mut tokens)
     }
 
-    fn parse_leader_req(tokens: &mut dyn Iterator<Item = &str>) -> Option<LeaderMessage> {
-        let _timestamp_str = tokens.next();
-        Some(LeaderMessage::LeaderElectionRequest {
-            timestamp: SystemTime::now(),
-        })
+    fn parse(
+        action: &str,
+        mut tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<LeaderMessage, MessageParseError> {
+        match action {
+            "le" => Ok(LeaderMessage::LeaderElectionRequest {
+                term: parse_field(&mut tokens, "term")?,
+            }),
+            "coordinator" => Ok(LeaderMessage::VictoryMessage {
+                term: parse_field(&mut tokens, "term")?,
+            }),
+            "ok" => Ok(LeaderMessage::OkMessage {
+                term: parse_field(&mut tokens, "term")?,
+            }),
+            other => Err(MessageParseError::UnknownMessage(other.to_owned())),
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum LockMessage {
-    Acquire,
+    Acquire { request_id: RequestId },
     Release,
 }
 
 impl Serializable for LockMessage {
     fn serialize(&self) -> String {
         match self {
-            LockMessage::Acquire => "lock_acquire\n".to_owned(),
+            LockMessage::Acquire { request_id } => format!("lock_acquire {}\n", request_id),
             LockMessage::Release => "lock_release\n".to_owned(),
         }
     }
 
-    fn deserialize(line: &str) -> Option<LockMessage> {
-        let mut tokens = line.split_whitespace();
-        let action = tokens.next();
+    fn deserialize(line: &str) -> Result<LockMessage, MessageParseError> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.iter().map(String::as_str);
+        let action = next_action(&mut tokens)?;
+        LockMessage::parse(action, &mut tokens)
+    }
+}
+
+impl LockMessage {
+    fn parse(
+        action: &str,
+        mut tokens: &mut dyn Iterator<Item = &str>,
+    ) -> Result<LockMessage, MessageParseError> {
         match action {
-            Some("lock_acquire") => Some(LockMessage::Acquire),
-            Some("lock_release") => Some(LockMessage::Release),
-            _ => None,
+            "lock_acquire" => Ok(LockMessage::Acquire {
+                request_id: parse_field(&mut tokens, "request id")?,
+            }),
+            "lock_release" => Ok(LockMessage::Release),
+            other => Err(MessageParseError::UnknownMessage(other.to_owned())),
+        }
+    }
+}
+
+// Tipos de los mensajes binarios; cada familia usa su propio rango
+const RB_REQUEST_TAG: u8 = 0x01;
+const RB_RESPONSE_TAG: u8 = 0x02;
+const WB_REQUEST_TAG: u8 = 0x03;
+const WB_RESPONSE_TAG: u8 = 0x04;
+const LOCK_RESPONSE_TAG: u8 = 0x05;
+const ERROR_TAG: u8 = 0x06;
+const APPEND_BLOCK_TAG: u8 = 0x07;
+const BLOCKS_REQUEST_TAG: u8 = 0x08;
+const BLOCKS_RESPONSE_TAG: u8 = 0x09;
+const SYNC_STATUS_REQUEST_TAG: u8 = 0x0a;
+const SYNC_STATUS_TAG: u8 = 0x0b;
+const LEADER_ELECTION_FINISHED_TAG: u8 = 0x0c;
+const KNOWN_PEERS_TAG: u8 = 0x0d;
+const HEARTBEAT_TAG: u8 = 0x0e;
+const DUPLICATE_CONNECTION_TAG: u8 = 0x0f;
+const LEADER_ELECTION_TAG: u8 = 0x20;
+const OK_TAG: u8 = 0x21;
+const COORDINATOR_TAG: u8 = 0x22;
+const LOCK_ACQUIRE_TAG: u8 = 0x30;
+const LOCK_RELEASE_TAG: u8 = 0x31;
+
+fn unknown_tag(tag: u8) -> MessageParseError {
+    MessageParseError::UnknownMessage(format!("{:#04x}", tag))
+}
+
+fn read_tag(input: &mut ByteReader) -> Result<u8, MessageParseError> {
+    if input.is_empty() {
+        return Err(MessageParseError::Empty);
+    }
+    Ok(input.u8("message type")?)
+}
+
+impl BinarySerializable for Message {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Message::Common(message) => message.encode(out),
+            Message::Leader(message) => message.encode(out),
+            Message::Lock(message) => message.encode(out),
+        }
+    }
+
+    fn decode(input: &mut ByteReader) -> Result<Self, MessageParseError> {
+        match read_tag(input)? {
+            tag @ 0x01..=0x1f => ClientMessage::decode_body(tag, input).map(Message::Common),
+            tag @ 0x20..=0x2f => LeaderMessage::decode_body(tag, input).map(Message::Leader),
+            tag => LockMessage::decode_body(tag, input).map(Message::Lock),
+        }
+    }
+}
+
+impl BinarySerializable for ClientMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ClientMessage::ReadBlockchainRequest { request_id } => {
+                put_u8(out, RB_REQUEST_TAG);
+                put_u64(out, *request_id);
+            }
+            ClientMessage::ReadBlockchainResponse {
+                request_id,
+                blockchain,
+            } => {
+                put_u8(out, RB_RESPONSE_TAG);
+                put_u64(out, *request_id);
+                blockchain.encode(out);
+            }
+            ClientMessage::WriteBlockchainRequest {
+                request_id,
+                transactions,
+            } => {
+                put_u8(out, WB_REQUEST_TAG);
+                put_u64(out, *request_id);
+                Transaction::encode_all(transactions, out);
+            }
+            ClientMessage::WriteBlockchainResponse {
+                request_id,
+                term,
+                transactions,
+            } => {
+                put_u8(out, WB_RESPONSE_TAG);
+                put_u64(out, *request_id);
+                put_u64(out, *term);
+                Transaction::encode_all(transactions, out);
+            }
+            ClientMessage::LockResponse {
+                request_id,
+                acquired,
+            } => {
+                put_u8(out, LOCK_RESPONSE_TAG);
+                put_u64(out, *request_id);
+                put_u8(out, *acquired as u8);
+            }
+            ClientMessage::ErrorResponse { request_id, error } => {
+                put_u8(out, ERROR_TAG);
+                match request_id {
+                    Some(id) => {
+                        put_u8(out, 1);
+                        put_u64(out, *id);
+                    }
+                    None => put_u8(out, 0),
+                }
+                match error {
+                    ErrorMessage::NotLeaderError => put_u8(out, 0),
+                    ErrorMessage::LockNotAcquiredError => put_u8(out, 1),
+                    ErrorMessage::InvalidBlockchainError => put_u8(out, 2),
+                    ErrorMessage::RejectedTransactionError(error) => {
+                        put_u8(out, 3);
+                        error.encode(out);
+                    }
+                    ErrorMessage::MalformedMessageError(reason) => {
+                        put_u8(out, 4);
+                        put_str(out, reason);
+                    }
+                }
+            }
+            ClientMessage::LeaderElectionFinished => put_u8(out, LEADER_ELECTION_FINISHED_TAG),
+            ClientMessage::AppendBlock { block } => {
+                put_u8(out, APPEND_BLOCK_TAG);
+                block.encode(out);
+            }
+            ClientMessage::BlocksRequest { from } => {
+                put_u8(out, BLOCKS_REQUEST_TAG);
+                put_u64(out, *from as u64);
+            }
+            ClientMessage::BlocksResponse {
+                from,
+                height,
+                blocks,
+            } => {
+                put_u8(out, BLOCKS_RESPONSE_TAG);
+                put_u64(out, *from as u64);
+                put_u64(out, *height as u64);
+                put_u32(out, blocks.len() as u32);
+                for block in blocks {
+                    block.encode(out);
+                }
+            }
+            ClientMessage::SyncStatusRequest => put_u8(out, SYNC_STATUS_REQUEST_TAG),
+            ClientMessage::SyncStatus { height, tip } => {
+                put_u8(out, SYNC_STATUS_TAG);
+                put_u64(out, *height as u64);
+                out.extend_from_slice(tip.as_bytes());
+            }
+            ClientMessage::KnownPeers { peers } => {
+                put_u8(out, KNOWN_PEERS_TAG);
+                put_u32(out, peers.len() as u32);
+                for (id, addr) in peers {
+                    put_u32(out, *id);
+                    put_str(out, &addr.to_string());
+                }
+            }
+            ClientMessage::Heartbeat => put_u8(out, HEARTBEAT_TAG),
+            ClientMessage::DuplicateConnection => put_u8(out, DUPLICATE_CONNECTION_TAG),
+        }
+    }
+
+    fn decode(input: &mut ByteReader) -> Result<Self, MessageParseError> {
+        let tag = read_tag(input)?;
+        ClientMessage::decode_body(tag, input)
+    }
+}
+
+impl ClientMessage {
+    fn decode_body(tag: u8, input: &mut ByteReader) -> Result<ClientMessage, MessageParseError> {
+        match tag {
+            RB_REQUEST_TAG => Ok(ClientMessage::ReadBlockchainRequest {
+                request_id: input.u64("request id")?,
+            }),
+            RB_RESPONSE_TAG => Ok(ClientMessage::ReadBlockchainResponse {
+                request_id: input.u64("request id")?,
+                blockchain: Blockchain::decode(input)?,
+            }),
+            WB_REQUEST_TAG => Ok(ClientMessage::WriteBlockchainRequest {
+                request_id: input.u64("request id")?,
+                transactions: Transaction::decode_all(input)?,
+            }),
+            WB_RESPONSE_TAG => Ok(ClientMessage::WriteBlockchainResponse {
+                request_id: input.u64("request id")?,
+                term: input.u64("term")?,
+                transactions: Transaction::decode_all(input)?,
+            }),
+            LOCK_RESPONSE_TAG => Ok(ClientMessage::LockResponse {
+                request_id: input.u64("request id")?,
+                acquired: input.u8("lock acquired")? != 0,
+            }),
+            ERROR_TAG => {
+                let request_id = match input.u8("request id")? {
+                    0 => None,
+                    _ => Some(input.u64("request id")?),
+                };
+                let error = match input.u8("error kind")? {
+                    0 => ErrorMessage::NotLeaderError,
+                    1 => ErrorMessage::LockNotAcquiredError,
+                    2 => ErrorMessage::InvalidBlockchainError,
+                    3 => ErrorMessage::RejectedTransactionError(ValidationError::decode(input)?),
+                    4 => ErrorMessage::MalformedMessageError(input.str("reason")?.to_owned()),
+                    other => {
+                        return Err(MessageParseError::Field(FieldError::Invalid {
+                            field: "error kind",
+                            value: other.to_string(),
+                        }))
+                    }
+                };
+                Ok(ClientMessage::ErrorResponse { request_id, error })
+            }
+            LEADER_ELECTION_FINISHED_TAG => Ok(ClientMessage::LeaderElectionFinished),
+            APPEND_BLOCK_TAG => Ok(ClientMessage::AppendBlock {
+                block: Block::decode(input)?,
+            }),
+            BLOCKS_REQUEST_TAG => Ok(ClientMessage::BlocksRequest {
+                from: input.usize("from height")?,
+            }),
+            BLOCKS_RESPONSE_TAG => {
+                let from = input.usize("from height")?;
+                let height = input.usize("height")?;
+                let count = input.u32("block count")?;
+                let mut blocks = Vec::new();
+                for _ in 0..count {
+                    blocks.push(Block::decode(input)?);
+                }
+                Ok(ClientMessage::BlocksResponse {
+                    from,
+                    height,
+                    blocks,
+                })
+            }
+            SYNC_STATUS_REQUEST_TAG => Ok(ClientMessage::SyncStatusRequest),
+            SYNC_STATUS_TAG => Ok(ClientMessage::SyncStatus {
+                height: input.usize("height")?,
+                tip: input.hash("tip")?,
+            }),
+            KNOWN_PEERS_TAG => {
+                let count = input.u32("peer count")?;
+                let mut peers = Vec::new();
+                for _ in 0..count {
+                    let id = input.u32("peer id")?;
+                    let addr = input.str("peer address")?;
+                    let addr = addr.parse().map_err(|_| FieldError::Invalid {
+                        field: "peer address",
+                        value: addr.to_owned(),
+                    })?;
+                    peers.push((id, addr));
+                }
+                Ok(ClientMessage::KnownPeers { peers })
+            }
+            HEARTBEAT_TAG => Ok(ClientMessage::Heartbeat),
+            DUPLICATE_CONNECTION_TAG => Ok(ClientMessage::DuplicateConnection),
+            other => Err(unknown_tag(other)),
+        }
+    }
+}
+
+impl BinarySerializable for LeaderMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            LeaderMessage::LeaderElectionRequest { term } => {
+                put_u8(out, LEADER_ELECTION_TAG);
+                put_u64(out, *term);
+            }
+            LeaderMessage::OkMessage { term } => {
+                put_u8(out, OK_TAG);
+                put_u64(out, *term);
+            }
+            LeaderMessage::VictoryMessage { term } => {
+                put_u8(out, COORDINATOR_TAG);
+                put_u64(out, *term);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn decode(input: &mut ByteReader) -> Result<Self, MessageParseError> {
+        let tag = read_tag(input)?;
+        LeaderMessage::decode_body(tag, input)
+    }
+}
+
+impl LeaderMessage {
+    fn decode_body(tag: u8, input: &mut ByteReader) -> Result<LeaderMessage, MessageParseError> {
+        match tag {
+            LEADER_ELECTION_TAG => Ok(LeaderMessage::LeaderElectionRequest {
+                term: input.u64("term")?,
+            }),
+            OK_TAG => Ok(LeaderMessage::OkMessage {
+                term: input.u64("term")?,
+            }),
+            COORDINATOR_TAG => Ok(LeaderMessage::VictoryMessage {
+                term: input.u64("term")?,
+            }),
+            other => Err(unknown_tag(other)),
+        }
+    }
+}
+
+impl BinarySerializable for LockMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            LockMessage::Acquire { request_id } => {
+                put_u8(out, LOCK_ACQUIRE_TAG);
+                put_u64(out, *request_id);
+            }
+            LockMessage::Release => put_u8(out, LOCK_RELEASE_TAG),
         }
     }
+
+    fn decode(input: &mut ByteReader) -> Result<Self, MessageParseError> {
+        let tag = read_tag(input)?;
+        LockMessage::decode_body(tag, input)
+    }
+}
+
+impl LockMessage {
+    fn decode_body(tag: u8, input: &mut ByteReader) -> Result<LockMessage, MessageParseError> {
+        match tag {
+            LOCK_ACQUIRE_TAG => Ok(LockMessage::Acquire {
+                request_id: input.u64("request id")?,
+            }),
+            LOCK_RELEASE_TAG => Ok(LockMessage::Release),
+            other => Err(unknown_tag(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::parse_error::TransactionParseError;
+    use crate::communication::serialization::{
+        BadFrame, FrameReader, LineReader, ReadError, WireFormat, MAX_FRAME_SIZE,
+    };
+
+    #[test]
+    fn malformed_write_is_an_error_not_a_panic() {
+        let err = Message::deserialize("wb 1 insert pedro algo1 parcial abc").unwrap_err();
+        assert!(matches!(
+            err,
+            MessageParseError::Transaction(TransactionParseError::Field(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_and_empty_lines_are_reported() {
+        assert_eq!(
+            Message::deserialize("hola mundo").unwrap_err(),
+            MessageParseError::UnknownMessage("hola".to_owned())
+        );
+        assert_eq!(
+            Message::deserialize("").unwrap_err(),
+            MessageParseError::Empty
+        );
+    }
+
+    #[test]
+    fn malformed_error_reply_always_parses() {
+        let reason = MessageParseError::UnknownMessage("hola".to_owned()).to_string();
+        let reply = ClientMessage::ErrorResponse {
+            request_id: None,
+            error: ErrorMessage::MalformedMessageError(reason.clone()),
+        };
+        let parsed = Message::deserialize(reply.serialize().trim_end()).unwrap();
+        match parsed {
+            Message::Common(ClientMessage::ErrorResponse {
+                request_id: None,
+                error: ErrorMessage::MalformedMessageError(parsed),
+            }) => assert_eq!(parsed, reason),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    /// Error de un mensaje inválido; falla si la lectura se cortó o el mensaje era válido
+    fn bad_frame(result: &Result<Message, ReadError>) -> &BadFrame {
+        match result {
+            Err(ReadError::Malformed(bad)) => bad,
+            other => panic!("expected a malformed message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn line_reader_keeps_reading_after_a_bad_line() {
+        let mut input = b"rb 1\nwb 2 insert pedro\nfuture_message 3\n".to_vec();
+        input.extend(b"rb \xff\xfe\r\nlock_acquire 3\n");
+        let messages: Vec<_> = LineReader::<_, Message>::new(input.as_slice()).collect();
+        assert_eq!(messages.len(), 5);
+        assert!(messages[0].is_ok());
+
+        let bad = bad_frame(&messages[1]);
+        assert_eq!(bad.position(), 2);
+        assert!(matches!(bad.error(), MessageParseError::Transaction(_)));
+        assert!(bad.to_string().contains("wb 2 insert pedro"));
+
+        let unknown = bad_frame(&messages[2]);
+        assert_eq!(
+            unknown.error(),
+            &MessageParseError::UnknownMessage("future_message".to_owned())
+        );
+        assert_eq!(
+            bad_frame(&messages[3]).error(),
+            &MessageParseError::InvalidUtf8
+        );
+        assert!(matches!(
+            messages[4],
+            Ok(Message::Lock(LockMessage::Acquire { request_id: 3 }))
+        ));
+    }
+
+    #[test]
+    fn read_errors_end_the_stream() {
+        struct Failing(bool);
+        impl std::io::Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Err(std::io::ErrorKind::ConnectionReset.into());
+                }
+                buf[..3].copy_from_slice(b"rb ");
+                Ok(3)
+            }
+        }
+        let messages: Vec<_> = LineReader::<_, Message>::new(Failing(false)).collect();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], Err(ReadError::Io(_))));
+    }
+
+    #[test]
+    fn block_messages_round_trip() {
+        let mut blockchain = Blockchain::new();
+        for student in ["pedro", "Juan Pablo"].iter() {
+            let data =
+                crate::blockchain::blockchain::TransactionData::new(student, "algo1", "final", 7);
+            blockchain.add_transactions(vec![Transaction::Insert(data)], 1, 1);
+        }
+        let block = blockchain.get_last().unwrap().clone();
+        let append = ClientMessage::AppendBlock {
+            block: block.clone(),
+        };
+        match Message::deserialize(append.serialize().trim_end()) {
+            Ok(Message::Common(ClientMessage::AppendBlock { block: parsed })) => {
+                assert_eq!(parsed, block)
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let response = ClientMessage::BlocksResponse {
+            from: 0,
+            height: 2,
+            blocks: blockchain.blocks().to_vec(),
+        };
+        match Message::deserialize(response.serialize().trim_end()) {
+            Ok(Message::Common(ClientMessage::BlocksResponse {
+                from,
+                height,
+                blocks,
+            })) => {
+                assert_eq!((from, height), (0, 2));
+                assert_eq!(blocks, blockchain.blocks());
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert!(matches!(
+            Message::deserialize("blocks_request 5"),
+            Ok(Message::Common(ClientMessage::BlocksRequest { from: 5 }))
+        ));
+        assert!(Message::deserialize("blocks 0 2 2 block").is_err());
+
+        let tip = *block.hash();
+        let status = ClientMessage::SyncStatus { height: 2, tip };
+        assert!(matches!(
+            Message::deserialize(status.serialize().trim_end()),
+            Ok(Message::Common(ClientMessage::SyncStatus { height: 2, tip: parsed })) if parsed == tip
+        ));
+    }
+
+    fn all_messages() -> Vec<Message> {
+        use crate::blockchain::blockchain::{GradeKey, TransactionData};
+        let mut blockchain = Blockchain::new();
+        let data = TransactionData::new("Juan Pablo", "algo1", "final", 7);
+        let key = GradeKey::new("日本 語", "tp 1", "\"q\"");
+        let other = TransactionData::new("日本 語", "tp 1", "\"q\"", 10);
+        blockchain.add_transactions(
+            vec![
+                Transaction::Insert(data.clone()),
+                Transaction::Insert(other),
+            ],
+            1,
+            1,
+        );
+        let update = Transaction::Update { data, previous: 7 };
+        blockchain.add_transactions(vec![update, Transaction::Remove(key.clone())], 2, 1);
+        let block = blockchain.get_last().unwrap().clone();
+        let rejected = ValidationError::StalePrevious { key, current: 3 };
+        vec![
+            Message::Common(ClientMessage::ReadBlockchainRequest { request_id: 1 }),
+            Message::Common(ClientMessage::ReadBlockchainResponse {
+                request_id: 1,
+                blockchain: blockchain.clone(),
+            }),
+            Message::Common(ClientMessage::WriteBlockchainRequest {
+                request_id: 2,
+                transactions: block.transactions().to_vec(),
+            }),
+            Message::Common(ClientMessage::WriteBlockchainResponse {
+                request_id: 2,
+                term: 4,
+                transactions: block.transactions().to_vec(),
+            }),
+            Message::Common(ClientMessage::LockResponse {
+                request_id: 3,
+                acquired: true,
+            }),
+            Message::Common(ClientMessage::ErrorResponse {
+                request_id: Some(4),
+                error: ErrorMessage::NotLeaderError,
+            }),
+            Message::Common(ClientMessage::ErrorResponse {
+                request_id: Some(5),
+                error: ErrorMessage::RejectedTransactionError(rejected),
+            }),
+            Message::Common(ClientMessage::ErrorResponse {
+                request_id: None,
+                error: ErrorMessage::MalformedMessageError("bad \n line".to_owned()),
+            }),
+            Message::Common(ClientMessage::AppendBlock {
+                block: block.clone(),
+            }),
+            Message::Common(ClientMessage::BlocksRequest { from: 1 }),
+            Message::Common(ClientMessage::BlocksResponse {
+                from: 0,
+                height: 2,
+                blocks: blockchain.blocks().to_vec(),
+            }),
+            Message::Common(ClientMessage::SyncStatusRequest),
+            Message::Common(ClientMessage::SyncStatus {
+                height: 2,
+                tip: *block.hash(),
+            }),
+            Message::Common(ClientMessage::KnownPeers {
+                peers: vec![
+                    (2, "127.0.0.2:9000".parse().unwrap()),
+                    (7, "[::1]:9001".parse().unwrap()),
+                ],
+            }),
+            Message::Common(ClientMessage::KnownPeers { peers: vec![] }),
+            Message::Common(ClientMessage::Heartbeat),
+            Message::Common(ClientMessage::DuplicateConnection),
+            Message::Leader(LeaderMessage::LeaderElectionRequest { term: 3 }),
+            Message::Leader(LeaderMessage::OkMessage { term: 3 }),
+            Message::Leader(LeaderMessage::VictoryMessage { term: u64::MAX }),
+            Message::Lock(LockMessage::Acquire {
+                request_id: u64::MAX,
+            }),
+            Message::Lock(LockMessage::Release),
+        ]
+    }
+
+    #[test]
+    fn both_wire_formats_round_trip() {
+        let messages = all_messages();
+        for format in [WireFormat::Text, WireFormat::Binary].iter() {
+            let stream: Vec<u8> = messages.iter().flat_map(|m| format.encode(m)).collect();
+            let parsed: Vec<_> = format
+                .reader::<_, Message>(std::io::Cursor::new(stream))
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(parsed.len(), messages.len());
+            for (parsed, message) in parsed.iter().zip(messages.iter()) {
+                // Algunos mensajes llevan canales, que no implementan PartialEq; se comparan
+                // codificados de nuevo en los dos formatos, que incluyen todos los campos
+                for other in [WireFormat::Text, WireFormat::Binary].iter() {
+                    assert_eq!(other.encode(parsed), other.encode(message), "{:?}", message);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binary_frames_are_smaller_for_blocks() {
+        let message = &all_messages()[1];
+        assert!(WireFormat::Binary.encode(message).len() < WireFormat::Text.encode(message).len());
+    }
+
+    #[test]
+    fn bad_binary_frames_are_reported() {
+        let mut stream = vec![0, 0, 0, 2, 0x7f, 0];
+        stream.extend(vec![0, 0, 0, 1, LOCK_RELEASE_TAG]);
+        stream.extend(vec![0, 0, 0, 2, LOCK_RELEASE_TAG, 0]);
+        stream.extend(vec![0, 0, 0, 3, APPEND_BLOCK_TAG, 0, 0]);
+        stream.extend(vec![0, 0, 0, 0]);
+        let messages: Vec<_> = FrameReader::<_, Message>::new(stream.as_slice()).collect();
+        assert_eq!(messages.len(), 5);
+        let unknown = bad_frame(&messages[0]);
+        assert_eq!(
+            unknown.error(),
+            &MessageParseError::UnknownMessage("0x7f".to_owned())
+        );
+        assert_eq!(unknown.excerpt(), "[2 bytes: 7f 00]");
+        assert!(matches!(
+            messages[1],
+            Ok(Message::Lock(LockMessage::Release))
+        ));
+        assert_eq!(bad_frame(&messages[2]).position(), 3);
+        assert!(matches!(
+            bad_frame(&messages[3]).error(),
+            MessageParseError::Block(_)
+        ));
+        assert_eq!(bad_frame(&messages[4]).error(), &MessageParseError::Empty);
+
+        // Un frame cortado es un error de lectura, no un EOF limpio
+        let truncated = vec![0, 0, 0, 5, LOCK_ACQUIRE_TAG];
+        let messages: Vec<_> = FrameReader::<_, Message>::new(truncated.as_slice()).collect();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], Err(ReadError::Io(_))));
+        assert_eq!(FrameReader::<_, Message>::new(&[][..]).count(), 0);
+
+        // Un largo absurdo no reserva memoria y corta la conexión
+        let mut oversized = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes().to_vec();
+        oversized.extend(vec![0, 0, 0, 1, LOCK_ACQUIRE_TAG]);
+        let messages: Vec<_> = FrameReader::<_, Message>::new(oversized.as_slice()).collect();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], Err(ReadError::Io(_))));
+    }
+
+    #[test]
+    fn binary_is_used_only_when_both_offer_it() {
+        let binary = WireFormat::Binary.offered();
+        let text = WireFormat::Text.offered();
+        assert_eq!(WireFormat::negotiate(binary, binary), WireFormat::Binary);
+        assert_eq!(WireFormat::negotiate(binary, text), WireFormat::Text);
+        assert_eq!(WireFormat::negotiate(text, binary), WireFormat::Text);
+        assert_eq!(WireFormat::negotiate(binary, &[]), WireFormat::Text);
+        assert_eq!(WireFormat::default(), WireFormat::Text);
+    }
 }
@@ -1,11 +1,29 @@
-use crate::blockchain::blockchain::Transaction;
-use crate::communication::serialization::Serializable;
+use crate::blockchain::blockchain::{BlockRef, Transaction};
+use crate::blockchain::parse_error::{next_field, parse_field};
+use crate::communication::serialization::{next_action, MessageParseError, Serializable};
+use crate::communication::tokenizer::tokenize;
 
 #[derive(Clone, Debug)]
 pub enum UserCommand {
     Exit,
     ReadBlockchain,
-    WriteBlockchain(Transaction),
+    ReadBlockchainAt(BlockRef),
+    StudentHistory(String),
+    ReadChanges { from: usize, to: usize },
+    WriteBlockchain(Vec<Transaction>),
+}
+
+impl UserCommand {
+    /// Las consultas se responden a partir de la cadena que devuelve el líder
+    pub fn is_read(&self) -> bool {
+        matches!(
+            self,
+            UserCommand::ReadBlockchain
+                | UserCommand::ReadBlockchainAt(_)
+                | UserCommand::StudentHistory(_)
+                | UserCommand::ReadChanges { .. }
+        )
+    }
 }
 
 impl Serializable for UserCommand {
@@ -13,17 +31,78 @@ impl Serializable for UserCommand {
         unreachable!()
     }
 
-    fn deserialize(line: &str) -> Option<Self> {
-        let mut tokens = line.split_whitespace();
-        let action = tokens.next();
-        match action {
-            Some("rb") => Some(UserCommand::ReadBlockchain),
-            Some("wb") => {
-                let transaction = Transaction::parse(&mut tokens)?;
-                Some(UserCommand::WriteBlockchain(transaction))
+    fn deserialize(line: &str) -> Result<Self, MessageParseError> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.iter().map(String::as_str);
+        match next_action(&mut tokens)? {
+            "rb" => match tokens.next() {
+                None => Ok(UserCommand::ReadBlockchain),
+                Some(block) => Ok(UserCommand::ReadBlockchainAt(BlockRef::parse(
+                    &mut std::iter::once(block),
+                )?)),
+            },
+            "history" => {
+                let student = next_field(&mut tokens, "student")?;
+                Ok(UserCommand::StudentHistory(student.to_owned()))
+            }
+            "changes" => {
+                let from = parse_field(&mut tokens, "from height")?;
+                let to = parse_field(&mut tokens, "to height")?;
+                Ok(UserCommand::ReadChanges { from, to })
             }
-            Some("exit") => Some(UserCommand::Exit {}),
-            _ => None,
+            "wb" => {
+                let transactions = Transaction::parse_all(&mut tokens)?;
+                Ok(UserCommand::WriteBlockchain(transactions))
+            }
+            "exit" => Ok(UserCommand::Exit {}),
+            other => Err(MessageParseError::UnknownMessage(other.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::blockchain::TransactionData;
+
+    #[test]
+    fn names_with_spaces_can_be_quoted() {
+        let command =
+            UserCommand::deserialize(r#"wb insert "Juan Pablo" "análisis 2" final 8"#).unwrap();
+        match command {
+            UserCommand::WriteBlockchain(transactions) => assert_eq!(
+                transactions,
+                vec![Transaction::Insert(TransactionData::new(
+                    "Juan Pablo",
+                    "análisis 2",
+                    "final",
+                    8
+                ))]
+            ),
+            other => panic!("unexpected command {:?}", other),
         }
     }
+
+    #[test]
+    fn unterminated_quote_is_an_error() {
+        assert!(UserCommand::deserialize(r#"wb insert "Juan Pablo algo1 final 8"#).is_err());
+    }
+
+    #[test]
+    fn history_and_point_in_time_queries() {
+        assert!(matches!(
+            UserCommand::deserialize("rb 3"),
+            Ok(UserCommand::ReadBlockchainAt(BlockRef::Height(3)))
+        ));
+        assert!(matches!(
+            UserCommand::deserialize(r#"history "Juan Pablo""#),
+            Ok(UserCommand::StudentHistory(student)) if student == "Juan Pablo"
+        ));
+        assert!(matches!(
+            UserCommand::deserialize("changes 1 4"),
+            Ok(UserCommand::ReadChanges { from: 1, to: 4 })
+        ));
+        assert!(UserCommand::deserialize("changes 1").is_err());
+        assert!(UserCommand::deserialize("rb ultimo").is_err());
+    }
 }
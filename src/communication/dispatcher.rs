@@ -37,26 +37,37 @@ impl Dispatcher {
 
     pub fn dispatch(&self, event: ClientEvent) -> io::Result<()> {
         match event {
-            ClientEvent::Connection { .. } | ClientEvent::PeerDisconnected { .. } => {
+            ClientEvent::Connection { .. }
+            | ClientEvent::PeerConnected { .. }
+            | ClientEvent::PeerDisconnected { .. }
+            | ClientEvent::KnownPeers { .. }
+            | ClientEvent::DialFailed { .. }
+            | ClientEvent::Shutdown => {
                 self.peer_sender
                     .send(event)
-                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "peer sender error"))?;
+                    .map_err(|_| io::Error::other("peer sender error"))?;
             }
             ClientEvent::PeerMessage { message, peer_id } => match message {
+                // La lista de pares de otro nodo le interesa al hilo de pares
+                Message::Common(ClientMessage::KnownPeers { peers }) => {
+                    self.peer_sender
+                        .send(ClientEvent::KnownPeers { peers })
+                        .map_err(|_| io::Error::other("peer sender error"))?;
+                }
                 Message::Common(message) => {
-                    self.message_sender.send((message, peer_id)).map_err(|_| {
-                        io::Error::new(io::ErrorKind::Other, "message sender error")
-                    })?;
+                    self.message_sender
+                        .send((message, peer_id))
+                        .map_err(|_| io::Error::other("message sender error"))?;
                 }
                 Message::Leader(message) => {
                     self.leader_sender
                         .send((message, peer_id))
-                        .map_err(|_| io::Error::new(io::ErrorKind::Other, "leader sender error"))?;
+                        .map_err(|_| io::Error::other("leader sender error"))?;
                 }
                 Message::Lock(message) => match message {
-                    LockMessage::Acquire => {
+                    LockMessage::Acquire { request_id } => {
                         println!("acquire to {}", peer_id);
-                        self.lock_handler.acquire(peer_id)
+                        self.lock_handler.acquire(peer_id, request_id)
                     }
                     LockMessage::Release => self.lock_handler.release(peer_id),
                 },
@@ -69,21 +80,19 @@ impl Dispatcher {
                             message,
                             peer_id: leader_id,
                         };
-                        self.peer_sender.send(event).map_err(|_| {
-                            io::Error::new(io::ErrorKind::Other, "message sender error")
-                        })?;
+                        self.peer_sender
+                            .send(event)
+                            .map_err(|_| io::Error::other("message sender error"))?;
                     } else {
                         self.message_sender
                             .send((inner.clone(), self.id))
-                            .map_err(|_| {
-                                io::Error::new(io::ErrorKind::Other, "message sender error")
-                            })?;
+                            .map_err(|_| io::Error::other("message sender error"))?;
                     }
                 }
                 Message::Leader(message) => {
                     self.leader_sender
                         .send((message.clone(), 0))
-                        .map_err(|_| io::Error::new(io::ErrorKind::Other, "leader sender error"))?;
+                        .map_err(|_| io::Error::other("leader sender error"))?;
                 }
                 Message::Lock(message) => {
                     let leader_id = Dispatcher::retrieve_leader(&self.leader_sender);
@@ -94,9 +103,9 @@ impl Dispatcher {
                             message: Message::Lock(message.clone()),
                             peer_id: leader_id,
                         };
-                        self.peer_sender.send(event).map_err(|_| {
-                            io::Error::new(io::ErrorKind::Other, "peer sender error")
-                        })?;
+                        self.peer_sender
+                            .send(event)
+                            .map_err(|_| io::Error::other("peer sender error"))?;
                     }
                 }
             },
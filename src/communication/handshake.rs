@@ -0,0 +1,333 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+
+use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::hash::BlockHash;
+use crate::blockchain::parse_error::{next_field, parse_field, FieldError};
+use crate::blockchain::peer::PeerIdType;
+use crate::communication::serialization::WireFormat;
+
+/// Primer token del handshake; cualquier otra cosa no es un nodo
+pub const MAGIC: &str = "BLKCHN";
+pub const PROTOCOL_VERSION: u16 = 2;
+/// Versión más vieja con la que se puede hablar. La 1 mandaba sólo el pid.
+pub const MIN_PROTOCOL_VERSION: u16 = 2;
+/// Límite de la línea de handshake, para no leer sin fin de un cliente que no es un nodo
+const MAX_HANDSHAKE_LEN: usize = 1024;
+
+/// Altura y último hash de la cadena local. El hilo de mensajes la actualiza al cambiar la
+/// cadena y el handshake la anuncia.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainTip {
+    height: usize,
+    hash: BlockHash,
+}
+
+impl ChainTip {
+    pub fn new(height: usize, hash: BlockHash) -> Self {
+        Self { height, hash }
+    }
+
+    pub fn of(blockchain: &Blockchain) -> Self {
+        let hash = blockchain
+            .get_last()
+            .map_or_else(BlockHash::zero, |block| *block.hash());
+        Self::new(blockchain.blocks().len(), hash)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn hash(&self) -> BlockHash {
+        self.hash
+    }
+}
+
+/// Motivo por el que se rechaza una conexión
+#[derive(Debug)]
+pub enum HandshakeError {
+    Io(io::Error),
+    BadMagic(String),
+    Malformed(FieldError),
+    IncompatibleVersion(u16),
+    OwnConnection,
+}
+
+impl Error for HandshakeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HandshakeError::Io(err) => Some(err),
+            HandshakeError::Malformed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandshakeError::Io(err) => write!(f, "handshake failed: {}", err),
+            HandshakeError::BadMagic(token) => write!(f, "not a blockchain node: '{}'", token),
+            HandshakeError::Malformed(err) => write!(f, "malformed handshake: {}", err),
+            HandshakeError::IncompatibleVersion(version) => write!(
+                f,
+                "incompatible protocol version {} (supported {}..={})",
+                version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+            HandshakeError::OwnConnection => write!(f, "connection to ourselves"),
+        }
+    }
+}
+
+impl From<io::Error> for HandshakeError {
+    fn from(err: io::Error) -> Self {
+        HandshakeError::Io(err)
+    }
+}
+
+impl From<FieldError> for HandshakeError {
+    fn from(err: FieldError) -> Self {
+        HandshakeError::Malformed(err)
+    }
+}
+
+/// Presentación que cada extremo manda al conectarse:
+/// `BLKCHN <versión> <id> <dirección> <capacidades> <altura> <hash>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    version: u16,
+    node_id: PeerIdType,
+    listen_addr: SocketAddr,
+    capabilities: Vec<String>,
+    tip: ChainTip,
+}
+
+impl Handshake {
+    /// Handshake propio; se ofrecen como capacidades los formatos aceptados
+    pub fn new(
+        node_id: PeerIdType,
+        listen_addr: SocketAddr,
+        wire_format: WireFormat,
+        tip: ChainTip,
+    ) -> Self {
+        let capabilities = wire_format
+            .offered()
+            .iter()
+            .map(|format| format.name().to_owned())
+            .collect();
+        Self {
+            version: PROTOCOL_VERSION,
+            node_id,
+            listen_addr,
+            capabilities,
+            tip,
+        }
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn node_id(&self) -> PeerIdType {
+        self.node_id
+    }
+
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen_addr
+    }
+
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    pub fn tip(&self) -> ChainTip {
+        self.tip
+    }
+
+    /// Formatos de mensaje que ofrece el otro extremo; las capacidades desconocidas se ignoran
+    pub fn formats(&self) -> Vec<WireFormat> {
+        self.capabilities
+            .iter()
+            .filter_map(|name| WireFormat::parse(name))
+            .collect()
+    }
+
+    pub fn serialize(&self) -> String {
+        let capabilities = if self.capabilities.is_empty() {
+            "-".to_owned()
+        } else {
+            self.capabilities.join(",")
+        };
+        format!(
+            "{} {} {} {} {} {} {}\n",
+            MAGIC,
+            self.version,
+            self.node_id,
+            self.listen_addr,
+            capabilities,
+            self.tip.height,
+            self.tip.hash
+        )
+    }
+
+    pub fn parse(line: &str) -> Result<Self, HandshakeError> {
+        let mut tokens = line.split_whitespace();
+        let magic = tokens.next().unwrap_or_default();
+        if magic != MAGIC {
+            return Err(HandshakeError::BadMagic(magic.chars().take(16).collect()));
+        }
+        let version = parse_field(&mut tokens, "version")?;
+        if version < MIN_PROTOCOL_VERSION {
+            return Err(HandshakeError::IncompatibleVersion(version));
+        }
+        let node_id = parse_field(&mut tokens, "node id")?;
+        let listen_addr = parse_field(&mut tokens, "listen address")?;
+        let capabilities = match next_field(&mut tokens, "capabilities")? {
+            "-" => vec![],
+            list => list.split(',').map(str::to_owned).collect(),
+        };
+        let height = parse_field(&mut tokens, "height")?;
+        let hash = next_field(&mut tokens, "tip")?;
+        let hash = BlockHash::parse(hash).ok_or_else(|| FieldError::Invalid {
+            field: "tip",
+            value: hash.to_owned(),
+        })?;
+        Ok(Self {
+            version,
+            node_id,
+            listen_addr,
+            capabilities,
+            tip: ChainTip::new(height, hash),
+        })
+    }
+
+    /// Manda el handshake propio y valida el del otro extremo
+    pub fn exchange<S: Read + Write>(&self, stream: &mut S) -> Result<Handshake, HandshakeError> {
+        stream.write_all(self.serialize().as_bytes())?;
+        let theirs = Handshake::parse(&read_line(stream)?)?;
+        if theirs.node_id == self.node_id {
+            return Err(HandshakeError::OwnConnection);
+        }
+        Ok(theirs)
+    }
+}
+
+/// Lee byte a byte para no consumir los mensajes que el otro extremo mande a continuación
+fn read_line<R: Read>(source: &mut R) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+        if source.read(&mut byte)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed during handshake",
+            ));
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() == MAX_HANDSHAKE_LEN {
+            return Err(io::Error::other("handshake line too long"));
+        }
+        line.push(byte[0]);
+    }
+    String::from_utf8(line).map_err(|_| io::Error::other("handshake is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn handshake(node_id: PeerIdType, format: WireFormat) -> Handshake {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 9000 + node_id as u16));
+        Handshake::new(node_id, addr, format, ChainTip::new(3, BlockHash::zero()))
+    }
+
+    #[test]
+    fn handshake_round_trips() {
+        let ours = handshake(7, WireFormat::Binary);
+        let line = ours.serialize();
+        assert_eq!(Handshake::parse(line.trim_end()).unwrap(), ours);
+        assert_eq!(ours.formats(), [WireFormat::Binary, WireFormat::Text]);
+
+        let unknown = line.replace("binary,text", "binary,gossip,text");
+        let parsed = Handshake::parse(&unknown).unwrap();
+        assert_eq!(parsed.formats(), [WireFormat::Binary, WireFormat::Text]);
+    }
+
+    #[test]
+    fn strangers_and_old_versions_are_rejected() {
+        assert!(matches!(
+            Handshake::parse("GET / HTTP/1.1"),
+            Err(HandshakeError::BadMagic(_))
+        ));
+        assert!(matches!(
+            Handshake::parse("12345"),
+            Err(HandshakeError::BadMagic(_))
+        ));
+        let old = handshake(7, WireFormat::Text).serialize().replacen(
+            &PROTOCOL_VERSION.to_string(),
+            "1",
+            1,
+        );
+        assert!(matches!(
+            Handshake::parse(&old),
+            Err(HandshakeError::IncompatibleVersion(1))
+        ));
+        assert!(matches!(
+            Handshake::parse("BLKCHN 2 7 localhost"),
+            Err(HandshakeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn exchange_over_tcp_keeps_following_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let remote = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let theirs = handshake(2, WireFormat::Text).exchange(&mut stream);
+            stream.write_all(b"rb\n").unwrap();
+            theirs.unwrap()
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let theirs = handshake(1, WireFormat::Binary)
+            .exchange(&mut stream)
+            .unwrap();
+        assert_eq!(theirs.node_id(), 2);
+        assert_eq!(theirs.formats(), [WireFormat::Text]);
+        assert_eq!(read_line(&mut stream).unwrap(), "rb");
+        assert_eq!(remote.join().unwrap().node_id(), 1);
+
+        let mut own = std::io::Cursor::new(handshake(1, WireFormat::Text).serialize().into_bytes());
+        let mut echo = ReadWrite(&mut own, Vec::new());
+        assert!(matches!(
+            handshake(1, WireFormat::Text).exchange(&mut echo),
+            Err(HandshakeError::OwnConnection)
+        ));
+    }
+
+    /// Stream de prueba que lee de un buffer y escribe en otro
+    struct ReadWrite<'a>(&'a mut std::io::Cursor<Vec<u8>>, Vec<u8>);
+
+    impl Read for ReadWrite<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for ReadWrite<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
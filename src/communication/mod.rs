@@ -1,4 +1,6 @@
 pub mod client_event;
 pub mod commands;
 pub mod dispatcher;
+pub mod handshake;
 pub mod serialization;
+pub mod tokenizer;
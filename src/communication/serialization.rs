@@ -1,15 +1,202 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
 use std::io::{BufRead, BufReader, Read};
 use std::marker::PhantomData;
 
+use crate::blockchain::hash::{BlockHash, HASH_SIZE};
+use crate::blockchain::parse_error::{
+    BlockParseError, BlockchainParseError, FieldError, TransactionParseError,
+};
+use crate::communication::tokenizer::TokenError;
+
 pub trait Serializable {
     fn serialize(&self) -> String;
-    fn deserialize(line: &str) -> Option<Self>
+    fn deserialize(line: &str) -> Result<Self, MessageParseError>
     where
         Self: Sized;
 }
 
+/// Motivo por el que una línea recibida no es un mensaje válido
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageParseError {
+    Empty,
+    InvalidUtf8,
+    Token(TokenError),
+    UnknownMessage(String),
+    Field(FieldError),
+    Transaction(TransactionParseError),
+    Block(BlockParseError),
+    Blockchain(BlockchainParseError),
+}
+
+impl Error for MessageParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MessageParseError::Token(err) => Some(err),
+            MessageParseError::Field(err) => Some(err),
+            MessageParseError::Transaction(err) => Some(err),
+            MessageParseError::Block(err) => Some(err),
+            MessageParseError::Blockchain(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MessageParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MessageParseError::Empty => write!(f, "empty message"),
+            MessageParseError::InvalidUtf8 => write!(f, "message is not valid UTF-8"),
+            MessageParseError::Token(err) => write!(f, "{}", err),
+            MessageParseError::UnknownMessage(action) => write!(f, "unknown message '{}'", action),
+            MessageParseError::Field(err) => write!(f, "{}", err),
+            MessageParseError::Transaction(err) => write!(f, "{}", err),
+            MessageParseError::Block(err) => write!(f, "{}", err),
+            MessageParseError::Blockchain(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<TokenError> for MessageParseError {
+    fn from(err: TokenError) -> Self {
+        MessageParseError::Token(err)
+    }
+}
+
+impl From<FieldError> for MessageParseError {
+    fn from(err: FieldError) -> Self {
+        MessageParseError::Field(err)
+    }
+}
+
+impl From<TransactionParseError> for MessageParseError {
+    fn from(err: TransactionParseError) -> Self {
+        MessageParseError::Transaction(err)
+    }
+}
+
+impl From<BlockParseError> for MessageParseError {
+    fn from(err: BlockParseError) -> Self {
+        MessageParseError::Block(err)
+    }
+}
+
+impl From<BlockchainParseError> for MessageParseError {
+    fn from(err: BlockchainParseError) -> Self {
+        MessageParseError::Blockchain(err)
+    }
+}
+
+/// Toma la acción de un mensaje; sólo falla si la línea está vacía
+pub fn next_action<'a>(
+    tokens: &mut dyn Iterator<Item = &'a str>,
+) -> Result<&'a str, MessageParseError> {
+    tokens.next().ok_or(MessageParseError::Empty)
+}
+
+/// Largo máximo del fragmento de un mensaje inválido que se incluye al reportarlo
+const EXCERPT_LEN: usize = 48;
+
+/// Mensaje recibido que no se pudo interpretar, con su posición en el stream y un fragmento
+/// para poder reportarlo
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadFrame {
+    position: usize,
+    excerpt: String,
+    error: MessageParseError,
+}
+
+impl BadFrame {
+    fn from_text(position: usize, line: &str, error: MessageParseError) -> Self {
+        let mut excerpt: String = line.chars().take(EXCERPT_LEN).collect();
+        if excerpt.len() < line.len() {
+            excerpt.push_str("...");
+        }
+        Self {
+            position,
+            excerpt: format!("{:?}", excerpt),
+            error,
+        }
+    }
+
+    fn from_bytes(position: usize, bytes: &[u8], error: MessageParseError) -> Self {
+        let mut excerpt: Vec<_> = bytes
+            .iter()
+            .take(EXCERPT_LEN / 3)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        if bytes.len() > excerpt.len() {
+            excerpt.push("...".to_owned());
+        }
+        Self {
+            position,
+            excerpt: format!("[{} bytes: {}]", bytes.len(), excerpt.join(" ")),
+            error,
+        }
+    }
+
+    /// Número de mensaje dentro del stream, empezando en 1
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn excerpt(&self) -> &str {
+        &self.excerpt
+    }
+
+    pub fn error(&self) -> &MessageParseError {
+        &self.error
+    }
+}
+
+impl Error for BadFrame {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for BadFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "message {} {}: {}",
+            self.position, self.excerpt, self.error
+        )
+    }
+}
+
+/// Error al leer un stream de mensajes. Un mensaje inválido no corta la lectura; un error
+/// de entrada/salida la termina.
+#[derive(Debug)]
+pub enum ReadError {
+    Malformed(BadFrame),
+    Io(io::Error),
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Malformed(err) => Some(err),
+            ReadError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Malformed(err) => write!(f, "{}", err),
+            ReadError::Io(err) => write!(f, "read failed: {}", err),
+        }
+    }
+}
+
 pub struct LineReader<R, O> {
     reader: BufReader<R>,
+    position: usize,
+    done: bool,
     _phantom: PhantomData<O>,
 }
 
@@ -17,19 +204,302 @@ impl<R: Read, O> LineReader<R, O> {
     pub fn new(source: R) -> Self {
         let reader = BufReader::new(source);
         let _phantom = PhantomData;
-        Self { reader, _phantom }
+        Self {
+            reader,
+            position: 0,
+            done: false,
+            _phantom,
+        }
     }
 }
 
+/// Devuelve `None` sólo al terminar el stream. Una línea inválida se entrega como error y
+/// se puede seguir leyendo; un error de lectura se entrega una vez y termina el stream.
 impl<R: Read, O> Iterator for LineReader<R, O>
 where
     O: Serializable,
 {
-    type Item = O;
+    type Item = Result<O, ReadError>;
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        if self.done {
+            return None;
+        }
+        let mut line = Vec::new();
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(err) => {
+                self.done = true;
+                return Some(Err(ReadError::Io(err)));
+            }
+        }
+        self.position += 1;
+        let (line, message) = match std::str::from_utf8(&line) {
+            Ok(line) => {
+                let line = line.trim_end_matches(['\n', '\r']);
+                (line.into(), O::deserialize(line))
+            }
+            Err(_) => (
+                String::from_utf8_lossy(&line),
+                Err(MessageParseError::InvalidUtf8),
+            ),
+        };
+        Some(
+            message.map_err(|err| {
+                ReadError::Malformed(BadFrame::from_text(self.position, &line, err))
+            }),
+        )
+    }
+}
+
+/// Formato de los mensajes en una conexión. El texto se conserva para depurar; el binario
+/// manda cada mensaje como un frame con largo y tipo. Mientras no se negoció otro se usa
+/// texto, que entienden todos los nodos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Text,
+    Binary,
+}
+
+impl WireFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WireFormat::Text => "text",
+            WireFormat::Binary => "binary",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(WireFormat::Text),
+            "binary" => Some(WireFormat::Binary),
+            _ => None,
+        }
+    }
+
+    /// Formatos que se ofrecen al conectarse, en orden de preferencia
+    pub fn offered(&self) -> &'static [WireFormat] {
+        match self {
+            WireFormat::Text => &[WireFormat::Text],
+            WireFormat::Binary => &[WireFormat::Binary, WireFormat::Text],
+        }
+    }
+
+    /// Se usa binario sólo si ambos extremos lo ofrecen; así los dos eligen lo mismo
+    pub fn negotiate(ours: &[WireFormat], theirs: &[WireFormat]) -> WireFormat {
+        if ours.contains(&WireFormat::Binary) && theirs.contains(&WireFormat::Binary) {
+            WireFormat::Binary
+        } else {
+            WireFormat::Text
+        }
+    }
+
+    pub fn encode<O: Serializable + BinarySerializable>(&self, message: &O) -> Vec<u8> {
+        match self {
+            WireFormat::Text => message.serialize().into_bytes(),
+            WireFormat::Binary => {
+                let mut payload = Vec::new();
+                message.encode(&mut payload);
+                let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+                frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                frame.extend_from_slice(&payload);
+                frame
+            }
+        }
+    }
+
+    pub fn reader<R, O>(&self, source: R) -> Box<dyn Iterator<Item = Result<O, ReadError>>>
+    where
+        R: Read + 'static,
+        O: Serializable + BinarySerializable + 'static,
+    {
+        match self {
+            WireFormat::Text => Box::new(LineReader::new(source)),
+            WireFormat::Binary => Box::new(FrameReader::new(source)),
+        }
+    }
+}
+
+/// Codificación binaria: enteros big endian, textos con su largo (u32) adelante y un byte
+/// de tipo al comienzo de cada mensaje.
+pub trait BinarySerializable {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut ByteReader) -> Result<Self, MessageParseError>
+    where
+        Self: Sized;
+}
+
+pub fn put_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+pub fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Lee campos de un payload binario; quedarse sin bytes es un campo faltante
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8], FieldError> {
+        if self.bytes.len() < len {
+            return Err(FieldError::Missing(field));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self, field: &'static str) -> Result<u8, FieldError> {
+        Ok(self.take(1, field)?[0])
+    }
+
+    pub fn u16(&mut self, field: &'static str) -> Result<u16, FieldError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2, field)?);
+        Ok(u16::from_be_bytes(bytes))
+    }
+
+    pub fn u32(&mut self, field: &'static str) -> Result<u32, FieldError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4, field)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    pub fn u64(&mut self, field: &'static str) -> Result<u64, FieldError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8, field)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// Altura o cantidad codificada como u64
+    pub fn usize(&mut self, field: &'static str) -> Result<usize, FieldError> {
+        let value = self.u64(field)?;
+        usize::try_from(value).map_err(|_| FieldError::Invalid {
+            field,
+            value: value.to_string(),
+        })
+    }
+
+    pub fn str(&mut self, field: &'static str) -> Result<&'a str, FieldError> {
+        let len = self.u32(field)? as usize;
+        let bytes = self.take(len, field)?;
+        std::str::from_utf8(bytes).map_err(|_| FieldError::Invalid {
+            field,
+            value: String::from_utf8_lossy(bytes).into_owned(),
+        })
+    }
+
+    pub fn hash(&mut self, field: &'static str) -> Result<BlockHash, FieldError> {
+        let mut bytes = [0; HASH_SIZE];
+        bytes.copy_from_slice(self.take(HASH_SIZE, field)?);
+        Ok(BlockHash::new(bytes))
+    }
+}
+
+const FRAME_HEADER_SIZE: usize = 4;
+/// Límite para no reservar memoria por un largo corrupto o malicioso
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Lee mensajes binarios: cada frame es el largo del payload (u32 big endian) seguido del
+/// payload. Un payload inválido se entrega como error y se sigue con el próximo frame.
+pub struct FrameReader<R, O> {
+    source: R,
+    position: usize,
+    done: bool,
+    _phantom: PhantomData<O>,
+}
+
+impl<R: Read, O> FrameReader<R, O> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            position: 0,
+            done: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Lee el largo del próximo frame; `None` si el stream terminó justo entre dos frames
+    fn read_header(&mut self) -> io::Result<Option<usize>> {
+        let mut header = [0; FRAME_HEADER_SIZE];
+        let mut filled = 0;
+        while filled < FRAME_HEADER_SIZE {
+            match self.source.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Some(u32::from_be_bytes(header) as usize))
+    }
+
+    fn fail(&mut self, err: io::Error) -> Option<Result<O, ReadError>> {
+        self.done = true;
+        Some(Err(ReadError::Io(err)))
+    }
+}
+
+impl<R: Read, O> Iterator for FrameReader<R, O>
+where
+    O: BinarySerializable,
+{
+    type Item = Result<O, ReadError>;
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        let mut line = String::new();
-        self.reader.read_line(&mut line).ok()?;
-        line.pop();
-        O::deserialize(&line)
+        if self.done {
+            return None;
+        }
+        let len = match self.read_header() {
+            Ok(Some(len)) => len,
+            Ok(None) => return None,
+            Err(err) => return self.fail(err),
+        };
+        self.position += 1;
+        if len > MAX_FRAME_SIZE {
+            // Sin un largo confiable no se puede encontrar el próximo frame
+            let reason = format!("frame of {} bytes exceeds {}", len, MAX_FRAME_SIZE);
+            return self.fail(io::Error::new(io::ErrorKind::InvalidData, reason));
+        }
+        let mut payload = vec![0; len];
+        if let Err(err) = self.source.read_exact(&mut payload) {
+            return self.fail(err);
+        }
+        let mut input = ByteReader::new(&payload);
+        let message = match O::decode(&mut input) {
+            Ok(_) if !input.is_empty() => Err(MessageParseError::Field(FieldError::Invalid {
+                field: "frame",
+                value: "trailing bytes".to_owned(),
+            })),
+            message => message,
+        };
+        Some(message.map_err(|err| {
+            ReadError::Malformed(BadFrame::from_bytes(self.position, &payload, err))
+        }))
     }
 }
@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+/// Error al separar una línea en tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenError {
+    UnterminatedQuote,
+    InvalidEscape(char),
+    MissingSeparator,
+}
+
+impl Error for TokenError {}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::UnterminatedQuote => write!(f, "unterminated quoted field"),
+            TokenError::InvalidEscape(c) => write!(f, "invalid escape '\\{}'", c),
+            TokenError::MissingSeparator => write!(f, "missing space after quoted field"),
+        }
+    }
+}
+
+/// Prepara un campo de texto libre para el protocolo. Los campos vacíos, con espacios o que
+/// empiezan con comillas van entre comillas dobles, escapando `"`, `\`, `\n` y `\r`.
+pub fn quote(field: &str) -> Cow<'_, str> {
+    let needs_quotes =
+        field.is_empty() || field.starts_with('"') || field.chars().any(char::is_whitespace);
+    if !needs_quotes {
+        return Cow::Borrowed(field);
+    }
+    let mut quoted = String::with_capacity(field.len() + 2);
+    quoted.push('"');
+    for c in field.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Separa una línea por espacios respetando los campos entre comillas generados por `quote`
+pub fn tokenize(line: &str) -> Result<Vec<String>, TokenError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.peek() {
+            None => return Ok(tokens),
+            Some('"') => {
+                chars.next();
+                tokens.push(read_quoted(&mut chars)?);
+                if chars.next_if(|c| !c.is_whitespace()).is_some() {
+                    return Err(TokenError::MissingSeparator);
+                }
+            }
+            Some(_) => {
+                let mut token = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+}
+
+fn read_quoted(chars: &mut dyn Iterator<Item = char>) -> Result<String, TokenError> {
+    let mut token = String::new();
+    loop {
+        match chars.next().ok_or(TokenError::UnterminatedQuote)? {
+            '"' => return Ok(token),
+            '\\' => match chars.next().ok_or(TokenError::UnterminatedQuote)? {
+                '"' => token.push('"'),
+                '\\' => token.push('\\'),
+                'n' => token.push('\n'),
+                'r' => token.push('\r'),
+                other => return Err(TokenError::InvalidEscape(other)),
+            },
+            c => token.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(quote("pedro"), "pedro");
+        assert_eq!(quote("José"), "José");
+        assert_eq!(
+            tokenize("  wb insert  pedro\t7 ").unwrap(),
+            ["wb", "insert", "pedro", "7"]
+        );
+    }
+
+    #[test]
+    fn adversarial_fields_round_trip() {
+        let fields = [
+            "Juan Pablo",
+            "",
+            " ",
+            "\"",
+            "\"quoted\"",
+            "back\\slash\\",
+            "line\nbreak\r",
+            "tab\there",
+            "end_blockchain",
+            "日本 語",
+        ];
+        let line = fields
+            .iter()
+            .map(|field| quote(field))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(!line.contains('\n'));
+        assert_eq!(tokenize(&line).unwrap(), fields);
+    }
+
+    #[test]
+    fn malformed_quotes_are_errors() {
+        assert_eq!(tokenize("\"Juan"), Err(TokenError::UnterminatedQuote));
+        assert_eq!(tokenize("\"Juan\\"), Err(TokenError::UnterminatedQuote));
+        assert_eq!(tokenize("\"a\\tb\""), Err(TokenError::InvalidEscape('t')));
+        assert_eq!(tokenize("\"a\"b"), Err(TokenError::MissingSeparator));
+    }
+}
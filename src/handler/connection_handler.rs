@@ -1,74 +1,80 @@
 use std::io;
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use crate::blockchain::config::Config;
 use crate::communication::client_event::ClientEvent;
 use crate::communication::dispatcher::Dispatcher;
+use crate::handler::discovery_handler::DiscoveryHandler;
 use std::thread;
 
 #[derive(Debug)]
 pub struct ConnectionHandler {
+    local_addr: SocketAddr,
+    discovery: Option<DiscoveryHandler>,
+    /// Se apaga para que el hilo deje de aceptar conexiones
+    running: Arc<AtomicBool>,
     thread_handle: Option<thread::JoinHandle<()>>,
 }
 
 impl ConnectionHandler {
-    pub fn new(port_from: u16, port_to: u16, dispatcher: Dispatcher) -> Self {
+    /// Toma un puerto del rango antes de arrancar, para poder anunciarlo en el handshake. A
+    /// los demás nodos los llama el hilo de pares.
+    pub fn new(config: &Config, dispatcher: Dispatcher) -> io::Result<Self> {
+        let listener = ConnectionHandler::listen_in_range(config.bind_addr(), config.ports())?;
+        let local_addr = listener.local_addr()?;
+        let discovery = match config.discovery_group() {
+            Some(group) => Some(DiscoveryHandler::new(
+                config,
+                group,
+                local_addr,
+                dispatcher.clone(),
+            )?),
+            None => None,
+        };
+        let running = Arc::new(AtomicBool::new(true));
+        let still_running = running.clone();
         let thread_handle = Some(thread::spawn(move || {
-            ConnectionHandler::run(port_from, port_to, dispatcher).unwrap();
+            ConnectionHandler::listen_to_incoming(listener, &still_running, &dispatcher).unwrap();
         }));
-        ConnectionHandler { thread_handle }
+        Ok(ConnectionHandler {
+            local_addr,
+            discovery,
+            running,
+            thread_handle,
+        })
     }
 
-    fn run(port_from: u16, port_to: u16, dispatcher: Dispatcher) -> io::Result<()> {
-        let listener = ConnectionHandler::listen_in_range(port_from, port_to)?;
-        let own_port: u16 = listener.local_addr()?.port();
-        ConnectionHandler::do_broadcasting(port_from, port_to, own_port, &dispatcher)?;
-        ConnectionHandler::listen_to_incoming(listener, &dispatcher)?;
-        Ok(())
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
     }
 
-    fn do_broadcasting(
-        port_from: u16,
-        port_to: u16,
-        own_port: u16,
-        dispatcher: &Dispatcher,
-    ) -> io::Result<()> {
-        for stream in ConnectionHandler::broadcast(own_port, port_from, port_to) {
-            let event = ClientEvent::Connection {
-                stream,
-                incoming: false,
-            };
-            dispatcher.dispatch(event).unwrap();
-        }
-        Ok(())
-    }
-
-    fn broadcast(own_port: u16, port_from: u16, port_to: u16) -> Vec<TcpStream> {
-        let host = "localhost";
-        (port_from..port_to)
-            .into_iter()
-            .filter(|port| *port != own_port)
-            .map(|port| ((host), port))
-            .map(TcpStream::connect)
-            .flatten()
-            .collect()
+    /// Puerto UDP en el que se reciben anuncios, si el descubrimiento está activo
+    pub fn discovery_addr(&self) -> Option<SocketAddr> {
+        self.discovery.as_ref().map(DiscoveryHandler::local_addr)
     }
 
-    fn listen_in_range(port_from: u16, port_to: u16) -> io::Result<TcpListener> {
-        let mask = [127, 0, 0, 1];
-        let mut addrs = vec![];
-        for port in port_from..port_to {
-            addrs.push(SocketAddr::from((mask, port)))
-        }
+    fn listen_in_range(bind_addr: IpAddr, ports: RangeInclusive<u16>) -> io::Result<TcpListener> {
+        let addrs: Vec<_> = ports.map(|port| SocketAddr::new(bind_addr, port)).collect();
 
         match TcpListener::bind(&addrs[..]) {
             Ok(listener) => Ok(listener),
-            Err(_err) => Err(io::Error::new(io::ErrorKind::Other, "Pool not available")),
+            Err(_err) => Err(io::Error::other("Pool not available")),
         }
     }
 
-    fn listen_to_incoming(listener: TcpListener, dispatcher: &Dispatcher) -> io::Result<()> {
+    fn listen_to_incoming(
+        listener: TcpListener,
+        running: &AtomicBool,
+        dispatcher: &Dispatcher,
+    ) -> io::Result<()> {
         for connection in listener.incoming() {
             let stream = connection?;
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
             let event = ClientEvent::Connection {
                 stream,
                 incoming: true,
@@ -81,6 +87,118 @@ impl ConnectionHandler {
 
 impl Drop for ConnectionHandler {
     fn drop(&mut self) {
+        // `accept` no tiene timeout: una conexión propia despierta al hilo para que vea que
+        // tiene que terminar
+        self.running.store(false, Ordering::SeqCst);
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        TcpStream::connect(wake_addr).ok();
         let _ = self.thread_handle.take().unwrap().join();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::blockchain::Blockchain;
+    use crate::blockchain::lock::CentralizedLock;
+    use crate::blockchain::peer::PeerIdType;
+    use crate::communication::client_event::{ClientMessage, LeaderMessage};
+    use crate::communication::handshake::ChainTip;
+    use crate::handler::lock_handler::LockProcessor;
+    use crate::handler::peer_handler::PeerHandler;
+    use std::collections::HashSet;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Red de un nodo sin los hilos de mensajes ni de elección; lo que reciben queda en los
+    /// canales. Al soltarlo deja de aceptar conexiones y cierra las que tiene.
+    struct TestNode {
+        _connections: ConnectionHandler,
+        _peers: PeerHandler,
+        messages: Receiver<(ClientMessage, PeerIdType)>,
+        _leader: Receiver<(LeaderMessage, PeerIdType)>,
+    }
+
+    fn start_node(id: PeerIdType, bind: &str, seeds: &str) -> TestNode {
+        let mut config = Config::default();
+        config.set("id", &id.to_string()).unwrap();
+        config.set("bind", bind).unwrap();
+        config.set("ports", "9700-9701").unwrap();
+        config.set("seeds", seeds).unwrap();
+        let (peer_sender, peer_receiver) = channel();
+        let (message_sender, messages) = channel();
+        let (leader_sender, leader_receiver) = channel();
+        let (output_sender, _) = channel();
+        let lock = CentralizedLock::new(config.lock_expiration());
+        let lock_handler = LockProcessor::new(
+            peer_sender.clone(),
+            Arc::new((Mutex::new(lock), Condvar::new())),
+        );
+        let dispatcher = Dispatcher::new(
+            id,
+            peer_sender,
+            message_sender,
+            leader_sender,
+            output_sender,
+            lock_handler,
+        );
+        let connections = ConnectionHandler::new(&config, dispatcher.clone()).unwrap();
+        let tip = Arc::new(Mutex::new(ChainTip::of(&Blockchain::new())));
+        let peers = PeerHandler::new(
+            &config,
+            connections.local_addr(),
+            tip,
+            peer_receiver,
+            dispatcher,
+        );
+        TestNode {
+            _connections: connections,
+            _peers: peers,
+            messages,
+            _leader: leader_receiver,
+        }
+    }
+
+    /// Cada par conectado le manda su punta al hilo de mensajes
+    fn connected_peers(node: &TestNode, expected: usize) -> HashSet<PeerIdType> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut peers = HashSet::new();
+        while peers.len() < expected {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match node.messages.recv_timeout(left) {
+                Ok((ClientMessage::SyncStatus { .. }, peer_id)) => {
+                    peers.insert(peer_id);
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        peers
+    }
+
+    #[test]
+    fn full_mesh_forms_from_a_single_seed() {
+        let a = start_node(1, "127.0.0.2", "");
+        let b = start_node(2, "127.0.0.3", "127.0.0.2:9700");
+        // C sólo conoce a B; de A se entera por la lista de pares
+        let c = start_node(3, "127.0.0.4", "127.0.0.3:9700");
+        let expected = |ids: &[PeerIdType]| ids.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(connected_peers(&a, 2), expected(&[2, 3]));
+        assert_eq!(connected_peers(&b, 2), expected(&[1, 3]));
+        assert_eq!(connected_peers(&c, 2), expected(&[1, 2]));
+    }
+
+    #[test]
+    fn seeds_that_start_later_are_dialed_again() {
+        let b = start_node(2, "127.0.0.6", "127.0.0.5:9700");
+        // La semilla todavía no escucha y el primer intento de B falla
+        thread::sleep(Duration::from_millis(100));
+        let a = start_node(1, "127.0.0.5", "");
+        assert_eq!(connected_peers(&a, 1), HashSet::from([2]));
+        assert_eq!(connected_peers(&b, 1), HashSet::from([1]));
+    }
+}
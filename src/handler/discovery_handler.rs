@@ -0,0 +1,309 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::blockchain::config::Config;
+use crate::blockchain::peer::PeerIdType;
+use crate::communication::client_event::ClientEvent;
+use crate::communication::dispatcher::Dispatcher;
+
+/// Primer token de los anuncios; los datagramas que no lo tienen se ignoran
+const ANNOUNCE_MAGIC: &str = "BLKCHN-ANNOUNCE";
+const MAX_ANNOUNCE_LEN: usize = 512;
+/// `set_read_timeout` no acepta una espera nula
+const MIN_WAIT: Duration = Duration::from_millis(1);
+/// Pausa tras un error al recibir, para no girar en vacío si se repite
+const ERROR_PAUSE: Duration = Duration::from_millis(100);
+
+/// Datagrama con el que un nodo avisa que existe: `BLKCHN-ANNOUNCE <cluster> <id> <dirección>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announce {
+    cluster: String,
+    node_id: PeerIdType,
+    listen_addr: SocketAddr,
+}
+
+impl Announce {
+    pub fn new(cluster: &str, node_id: PeerIdType, listen_addr: SocketAddr) -> Self {
+        Self {
+            cluster: cluster.to_owned(),
+            node_id,
+            listen_addr,
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            ANNOUNCE_MAGIC, self.cluster, self.node_id, self.listen_addr
+        )
+    }
+
+    pub fn parse(datagram: &[u8]) -> Option<Self> {
+        let datagram = std::str::from_utf8(datagram).ok()?;
+        let mut tokens = datagram.split_whitespace();
+        if tokens.next()? != ANNOUNCE_MAGIC {
+            return None;
+        }
+        let announce = Self {
+            cluster: tokens.next()?.to_owned(),
+            node_id: tokens.next()?.parse().ok()?,
+            listen_addr: tokens.next()?.parse().ok()?,
+        };
+        tokens.next().is_none().then_some(announce)
+    }
+}
+
+/// Anuncia el nodo por UDP y le pasa al hilo de pares los nodos del mismo cluster que se
+/// anuncian.
+///
+/// Lo natural sería un único puerto conocido en el que escuchen todos, pero para que varios
+/// procesos de una máquina lo compartan hace falta `SO_REUSEADDR`, que la librería estándar
+/// no expone. Por eso cada nodo de una máquina toma un puerto del rango y los anuncios se
+/// mandan a todo el rango: los nodos tienen que usar el mismo rango y cada anuncio cuesta un
+/// datagrama por puerto, que con rangos de unos pocos puertos es poco.
+#[derive(Debug)]
+pub struct DiscoveryHandler {
+    local_addr: SocketAddr,
+    /// Se apaga para que el hilo deje de anunciar
+    running: Arc<AtomicBool>,
+    thread_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl DiscoveryHandler {
+    pub fn new(
+        config: &Config,
+        group: Ipv4Addr,
+        listen_addr: SocketAddr,
+        dispatcher: Dispatcher,
+    ) -> io::Result<Self> {
+        let socket = DiscoveryHandler::bind_in_range(config.discovery_ports())?;
+        if group.is_multicast() {
+            socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+            socket.set_multicast_loop_v4(true)?;
+        } else {
+            socket.set_broadcast(true)?;
+        }
+        let local_addr = socket.local_addr()?;
+        let announce = Announce::new(config.cluster(), config.node_id(), listen_addr);
+        let targets: Vec<_> = config
+            .discovery_ports()
+            .map(|port| SocketAddr::from((group, port)))
+            .collect();
+        let interval = config.announce_interval();
+        let running = Arc::new(AtomicBool::new(true));
+        let still_running = running.clone();
+        let thread_handle = Some(thread::spawn(move || {
+            let result = DiscoveryHandler::run(
+                socket,
+                announce,
+                targets,
+                interval,
+                &still_running,
+                dispatcher,
+            );
+            if let Err(err) = result {
+                error!("Discovery stopped: {}", err);
+            }
+        }));
+        Ok(DiscoveryHandler {
+            local_addr,
+            running,
+            thread_handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn bind_in_range(ports: RangeInclusive<u16>) -> io::Result<UdpSocket> {
+        let addrs: Vec<_> = ports
+            .map(|port| SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)))
+            .collect();
+        UdpSocket::bind(&addrs[..])
+            .map_err(|err| io::Error::other(format!("No discovery port available: {}", err)))
+    }
+
+    fn run(
+        socket: UdpSocket,
+        announce: Announce,
+        targets: Vec<SocketAddr>,
+        interval: Duration,
+        running: &AtomicBool,
+        dispatcher: Dispatcher,
+    ) -> io::Result<()> {
+        let datagram = announce.serialize();
+        let mut buffer = [0; MAX_ANNOUNCE_LEN];
+        let mut next_announce = Instant::now();
+        while running.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= next_announce {
+                for target in targets.iter() {
+                    if let Err(err) = socket.send_to(datagram.as_bytes(), target) {
+                        debug!("Could not announce to {}: {}", target, err);
+                    }
+                }
+                next_announce = now + interval;
+            }
+            let wait = next_announce.saturating_duration_since(Instant::now());
+            socket.set_read_timeout(Some(wait.max(MIN_WAIT)))?;
+            match socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    DiscoveryHandler::receive(&buffer[..len], from, &announce, &dispatcher)
+                }
+                Err(err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut => {}
+                // Un error al recibir (un ICMP de un puerto sin nodo, una interfaz que se cae)
+                // no tiene por qué repetirse; se sigue anunciando y escuchando
+                Err(err) => {
+                    warn!("Could not receive announces: {}", err);
+                    thread::sleep(ERROR_PAUSE);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(datagram: &[u8], from: SocketAddr, own: &Announce, dispatcher: &Dispatcher) {
+        let theirs = match Announce::parse(datagram) {
+            Some(theirs) => theirs,
+            None => {
+                debug!("Ignoring datagram from {}", from);
+                return;
+            }
+        };
+        if theirs.cluster != own.cluster || theirs.node_id == own.node_id {
+            return;
+        }
+        // Un nodo que escucha en todas las interfaces se alcanza por la que mandó el anuncio
+        let mut addr = theirs.listen_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(from.ip());
+        }
+        let event = ClientEvent::KnownPeers {
+            peers: vec![(theirs.node_id, addr)],
+        };
+        dispatcher.dispatch(event).ok();
+    }
+}
+
+impl Drop for DiscoveryHandler {
+    fn drop(&mut self) {
+        // Un datagrama propio despierta al hilo sin esperar al próximo anuncio
+        self.running.store(false, Ordering::SeqCst);
+        let wake_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, self.local_addr.port()));
+        if let Ok(socket) = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)) {
+            socket.send_to(&[], wake_addr).ok();
+        }
+        let _ = self.thread_handle.take().unwrap().join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::lock::CentralizedLock;
+    use crate::handler::lock_handler::LockProcessor;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::{Arc, Condvar, Mutex};
+
+    #[test]
+    fn announces_round_trip() {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 9000));
+        let announce = Announce::new("notas", 7, addr);
+        assert_eq!(
+            Announce::parse(announce.serialize().as_bytes()),
+            Some(announce)
+        );
+        assert_eq!(Announce::parse(b"BLKCHN-ANNOUNCE notas 7"), None);
+        assert_eq!(
+            Announce::parse(b"BLKCHN-ANNOUNCE notas x 10.0.0.1:9000"),
+            None
+        );
+        assert_eq!(Announce::parse(b"GET / HTTP/1.1"), None);
+        assert_eq!(Announce::parse(&[0xff, 0xfe]), None);
+    }
+
+    /// Descubrimiento de un nodo; los pares anunciados quedan en el canal
+    struct TestNode {
+        _discovery: DiscoveryHandler,
+        announced: Receiver<ClientEvent>,
+    }
+
+    fn start(id: PeerIdType, cluster: &str, group: &str, ports: &str, ip: [u8; 4]) -> TestNode {
+        let mut config = Config::default();
+        config.set("id", &id.to_string()).unwrap();
+        config.set("discovery", group).unwrap();
+        config.set("discovery-ports", ports).unwrap();
+        config.set("cluster", cluster).unwrap();
+        config.set("announce-interval", "100ms").unwrap();
+        let (peer_sender, peer_receiver) = channel();
+        let lock = CentralizedLock::new(config.lock_expiration());
+        let lock_handler = LockProcessor::new(
+            peer_sender.clone(),
+            Arc::new((Mutex::new(lock), Condvar::new())),
+        );
+        let dispatcher = Dispatcher::new(
+            id,
+            peer_sender,
+            channel().0,
+            channel().0,
+            channel().0,
+            lock_handler,
+        );
+        let listen_addr = SocketAddr::from((ip, 9000 + id as u16));
+        let group = config.discovery_group().unwrap();
+        TestNode {
+            _discovery: DiscoveryHandler::new(&config, group, listen_addr, dispatcher).unwrap(),
+            announced: peer_receiver,
+        }
+    }
+
+    /// Pares anunciados durante un segundo, varios intervalos de anuncio
+    fn announced(node: &TestNode) -> Vec<(PeerIdType, SocketAddr)> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut peers = vec![];
+        while let Ok(event) = node
+            .announced
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if let ClientEvent::KnownPeers { peers: announced } = event {
+                for peer in announced {
+                    if !peers.contains(&peer) {
+                        peers.push(peer);
+                    }
+                }
+            }
+        }
+        peers.sort();
+        peers
+    }
+
+    #[test]
+    fn nodes_of_the_same_cluster_find_each_other_on_loopback() {
+        let start = |id, cluster| start(id, cluster, "127.255.255.255", "9910-9913", [0; 4]);
+        let a = start(1, "notas");
+        let b = start(2, "notas");
+        let other = start(3, "otro");
+        let localhost = |id: u16| SocketAddr::from(([127, 0, 0, 1], 9000 + id));
+        assert_eq!(announced(&a), [(2, localhost(2))]);
+        assert_eq!(announced(&b), [(1, localhost(1))]);
+        assert_eq!(announced(&other), []);
+    }
+
+    #[test]
+    fn nodes_of_the_same_cluster_find_each_other_by_multicast() {
+        let start = |id| start(id, "notas", "239.255.42.98", "9920-9922", [127, 0, 0, 1]);
+        let a = start(1);
+        let b = start(2);
+        let localhost = |id: u16| SocketAddr::from(([127, 0, 0, 1], 9000 + id));
+        assert_eq!(announced(&a), [(2, localhost(2))]);
+        assert_eq!(announced(&b), [(1, localhost(1))]);
+    }
+}
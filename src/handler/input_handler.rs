@@ -1,8 +1,9 @@
+use crate::blockchain::blockchain::Blockchain;
 use crate::communication::client_event::{ClientEvent, ClientMessage, ErrorMessage};
-use crate::communication::client_event::{LockMessage, Message};
+use crate::communication::client_event::{LockMessage, Message, RequestId};
 use crate::communication::commands::UserCommand;
 use crate::communication::dispatcher::Dispatcher;
-use crate::communication::serialization::LineReader;
+use crate::communication::serialization::{LineReader, ReadError};
 use std::io::Read;
 use std::sync::mpsc::Receiver;
 
@@ -23,28 +24,44 @@ impl InputProcessor {
         let mut command_reader = LineReader::<R, UserCommand>::new(source);
         let mut status = ClientStatus::Idle;
         let mut current_command = None;
+        // Cada pedido, incluidos los reintentos y los pedidos de lock, lleva un id nuevo; sólo
+        // se acepta la respuesta al último
+        let mut last_request: RequestId = 0;
         loop {
             match status {
                 ClientStatus::Idle => {
                     println!("Ingrese un comando");
-                    current_command = command_reader.next();
+                    current_command = match command_reader.next() {
+                        Some(Ok(command)) => Some(command),
+                        Some(Err(ReadError::Malformed(bad))) => {
+                            println!("Comando inválido: {}", bad.error());
+                            continue;
+                        }
+                        Some(Err(ReadError::Io(err))) => {
+                            error!("No se pudo leer la entrada: {}", err);
+                            None
+                        }
+                        None => None,
+                    };
                     status = ClientStatus::SendCommand;
                 }
                 ClientStatus::SendCommand => {
-                    let message;
-                    match &current_command {
-                        Some(UserCommand::ReadBlockchain) => {
-                            message = Message::Common(ClientMessage::ReadBlockchainRequest)
+                    last_request += 1;
+                    let request_id = last_request;
+                    let message = match &current_command {
+                        Some(command) if command.is_read() => {
+                            Message::Common(ClientMessage::ReadBlockchainRequest { request_id })
                         }
-                        Some(UserCommand::WriteBlockchain(transaction)) => {
-                            message = Message::Common(ClientMessage::WriteBlockchainRequest {
-                                transaction: transaction.clone(),
+                        Some(UserCommand::WriteBlockchain(transactions)) => {
+                            Message::Common(ClientMessage::WriteBlockchainRequest {
+                                request_id,
+                                transactions: transactions.clone(),
                             })
                         }
                         _ => {
                             break;
                         }
-                    }
+                    };
                     let event = ClientEvent::UserInput { message };
                     self.dispatcher.dispatch(event).ok();
                     status = ClientStatus::WaitingReply;
@@ -53,20 +70,35 @@ impl InputProcessor {
                     info!("Waiting reply...");
                     let response = self.output_receiver.recv().unwrap();
                     info!("Input handler response -> {:?}", response);
+                    let expected = matches!(response, ClientMessage::LeaderElectionFinished)
+                        || response.request_id() == Some(last_request);
+                    if !expected {
+                        warn!(
+                            "Dropping response to request {:?}, waiting for {}",
+                            response.request_id(),
+                            last_request
+                        );
+                        continue;
+                    }
                     match response {
-                        ClientMessage::ErrorResponse(ErrorMessage::LockNotAcquiredError) => {
-                            let event = ClientEvent::UserInput {
-                                message: Message::Lock(LockMessage::Acquire),
-                            };
-                            self.dispatcher.dispatch(event).ok();
+                        ClientMessage::ErrorResponse {
+                            error: ErrorMessage::LockNotAcquiredError,
+                            ..
+                        } => {
+                            last_request += 1;
+                            self.send_lock(LockMessage::Acquire {
+                                request_id: last_request,
+                            });
                         }
-                        ClientMessage::LockResponse(true) => {
+                        ClientMessage::LockResponse { acquired: true, .. } => {
                             status = ClientStatus::SendCommand;
                         }
-                        ClientMessage::ReadBlockchainResponse { blockchain } => {
-                            if let Some(UserCommand::ReadBlockchain) = current_command {
-                                println!("Blockchain: {}", blockchain);
-                                status = ClientStatus::Idle;
+                        ClientMessage::ReadBlockchainResponse { blockchain, .. } => {
+                            if let Some(command) = &current_command {
+                                if command.is_read() {
+                                    InputProcessor::show_query(command, &blockchain);
+                                    status = ClientStatus::Idle;
+                                }
                             }
                         }
                         ClientMessage::WriteBlockchainResponse { .. } => {
@@ -74,24 +106,59 @@ impl InputProcessor {
                                 info!("Write blockchain exitoso");
                                 status = ClientStatus::Idle;
                             }
-                            let event = ClientEvent::UserInput {
-                                message: Message::Lock(LockMessage::Release),
-                            };
-                            self.dispatcher.dispatch(event).ok();
+                            self.send_lock(LockMessage::Release);
                         }
-                        ClientMessage::ReadBlockchainRequest => todo!(),
-                        ClientMessage::WriteBlockchainRequest { transaction: _ } => todo!(),
+                        ClientMessage::ReadBlockchainRequest { .. } => todo!(),
+                        ClientMessage::WriteBlockchainRequest { .. } => todo!(),
                         ClientMessage::LeaderElectionFinished => {
                             status = ClientStatus::SendCommand;
                         }
-                        ClientMessage::BroadcastBlockchain { blockchain: _ } => {}
-                        ClientMessage::LockResponse(_acquired) => {
-                            let event = ClientEvent::UserInput {
-                                message: Message::Lock(LockMessage::Acquire),
-                            };
-                            self.dispatcher.dispatch(event).ok();
+                        ClientMessage::AppendBlock { .. }
+                        | ClientMessage::BlocksRequest { .. }
+                        | ClientMessage::BlocksResponse { .. }
+                        | ClientMessage::SyncStatusRequest
+                        | ClientMessage::SyncStatus { .. }
+                        | ClientMessage::KnownPeers { .. }
+                        | ClientMessage::Heartbeat
+                        | ClientMessage::DuplicateConnection => {}
+                        ClientMessage::LockResponse {
+                            acquired: false, ..
+                        } => {
+                            last_request += 1;
+                            self.send_lock(LockMessage::Acquire {
+                                request_id: last_request,
+                            });
+                        }
+                        ClientMessage::ErrorResponse {
+                            error: ErrorMessage::InvalidBlockchainError,
+                            ..
+                        } => {
+                            if current_command.as_ref().is_some_and(UserCommand::is_read) {
+                                error!("Received blockchain failed verification");
+                                status = ClientStatus::Idle;
+                            }
+                        }
+                        ClientMessage::ErrorResponse {
+                            error: ErrorMessage::RejectedTransactionError(error),
+                            ..
+                        } => {
+                            if let Some(UserCommand::WriteBlockchain(_)) = current_command {
+                                error!("Write rejected: {}", error);
+                                status = ClientStatus::Idle;
+                            }
+                            self.send_lock(LockMessage::Release);
                         }
-                        ClientMessage::ErrorResponse(error) => {
+                        ClientMessage::ErrorResponse {
+                            error: ErrorMessage::MalformedMessageError(reason),
+                            ..
+                        } => {
+                            error!("Peer could not parse our message: {}", reason);
+                            if let Some(UserCommand::WriteBlockchain(_)) = current_command {
+                                self.send_lock(LockMessage::Release);
+                            }
+                            status = ClientStatus::Idle;
+                        }
+                        ClientMessage::ErrorResponse { error, .. } => {
                             error!("Error: {:?}", error);
                             error!("Retrying....");
                             status = ClientStatus::SendCommand;
@@ -102,9 +169,110 @@ impl InputProcessor {
         }
         error!("Saliendo de la aplicación");
     }
+
+    fn send_lock(&self, message: LockMessage) {
+        let event = ClientEvent::UserInput {
+            message: Message::Lock(message),
+        };
+        self.dispatcher.dispatch(event).ok();
+    }
+
+    /// Responde una consulta con la cadena recibida del líder
+    fn show_query(command: &UserCommand, blockchain: &Blockchain) {
+        match command {
+            UserCommand::ReadBlockchainAt(block) => {
+                match blockchain
+                    .resolve(block)
+                    .and_then(|height| blockchain.state_at(height))
+                {
+                    Some(state) => println!("Blockchain en el bloque {}: {}", block, state),
+                    None => println!("No existe el bloque {}", block),
+                }
+            }
+            UserCommand::StudentHistory(student) => {
+                println!("Historial de {}:", student);
+                for change in blockchain.history_of(student) {
+                    println!("{}", change);
+                }
+            }
+            UserCommand::ReadChanges { from, to } => match blockchain.changes_between(*from, *to) {
+                Some(changes) => {
+                    println!("Cambios entre los bloques {} y {}:", from, to);
+                    for change in changes {
+                        println!("{}", change);
+                    }
+                }
+                None => println!("Rango de bloques inválido: {} a {}", from, to),
+            },
+            _ => println!("Blockchain: {}", blockchain),
+        }
+    }
 }
 enum ClientStatus {
     Idle,
     SendCommand,
     WaitingReply,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::lock::CentralizedLock;
+    use crate::communication::client_event::LeaderMessage;
+    use crate::handler::lock_handler::LockProcessor;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn stale_responses_are_dropped() {
+        let (peer_sender, _peer_receiver) = channel();
+        let (message_sender, message_receiver) = channel();
+        let (leader_sender, leader_receiver) = channel();
+        let (output_sender, output_receiver) = channel();
+        let lock = Arc::new((
+            Mutex::new(CentralizedLock::new(Duration::from_secs(5))),
+            Condvar::new(),
+        ));
+        let lock_handler = LockProcessor::new(peer_sender.clone(), lock);
+        let dispatcher = Dispatcher::new(
+            1,
+            peer_sender,
+            message_sender,
+            leader_sender,
+            output_sender.clone(),
+            lock_handler,
+        );
+        // Este nodo es el líder y responde repitiendo el id del pedido
+        thread::spawn(move || {
+            for (message, _) in leader_receiver {
+                if let LeaderMessage::CurrentLeaderLocal { response_sender } = message {
+                    response_sender.send(1).ok();
+                }
+            }
+        });
+        let leader_output = output_sender.clone();
+        thread::spawn(move || {
+            for (message, _) in message_receiver {
+                if let ClientMessage::ReadBlockchainRequest { request_id } = message {
+                    let response = ClientMessage::ReadBlockchainResponse {
+                        request_id,
+                        blockchain: Blockchain::new(),
+                    };
+                    leader_output.send(response).ok();
+                }
+            }
+        });
+        let stale = ClientMessage::ReadBlockchainResponse {
+            request_id: 42,
+            blockchain: Blockchain::new(),
+        };
+        output_sender.send(stale).unwrap();
+
+        let processor = InputProcessor::new(output_receiver, dispatcher);
+        processor.run("rb\n".as_bytes());
+        // La respuesta vieja se descartó y la del pedido no quedó encolada
+        assert!(processor.output_receiver.try_recv().is_err());
+    }
+}
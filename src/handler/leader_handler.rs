@@ -1,13 +1,14 @@
 use std::sync::mpsc::{RecvTimeoutError, Sender};
 use std::sync::{Arc, Condvar, Mutex, MutexGuard};
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 use std::{io, sync::mpsc::Receiver, thread};
 
+use crate::blockchain::blockchain::TermType;
+use crate::blockchain::config::Config;
 use crate::blockchain::peer::PeerIdType;
+use crate::blockchain::storage::TermStore;
 use crate::communication::client_event::{ClientEvent, ClientMessage, LeaderMessage, Message};
 
-const LEADER_ELECTION_TIMEOUT: Duration = Duration::from_secs(5);
-
 #[derive(Debug)]
 pub struct LeaderHandler {
     thread_handle: Option<thread::JoinHandle<()>>,
@@ -17,7 +18,14 @@ struct LeaderProcessor {
     peer_handler_sender: Sender<ClientEvent>,
     output_sender: Sender<ClientMessage>,
     current_leader: PeerIdType,
+    /// Época en la que se eligió al líder actual; con ella se marcan los bloques
+    leader_term: TermType,
+    /// Época más alta vista; los mensajes de épocas anteriores se descartan
+    term: TermType,
+    term_store: Option<TermStore>,
     own_id: u32,
+    /// Espera de las respuestas de una elección
+    election_timeout: Duration,
     waiting_coordinator: bool,
     election_in_progress: bool,
     election_by_user: bool,
@@ -29,44 +37,45 @@ impl LeaderHandler {
         peer_handler_sender: Sender<ClientEvent>,
         output_sender: Sender<ClientMessage>,
         leader_election_notify: Arc<(Mutex<bool>, Condvar)>,
-        own_id: u32,
+        config: &Config,
+        term_store: Option<TermStore>,
+        term: TermType,
     ) -> Self {
+        let mut processor = LeaderProcessor::new(
+            config.node_id(),
+            config.election_timeout(),
+            peer_handler_sender,
+            output_sender,
+            term_store,
+            term,
+        );
         let thread_handle = Some(thread::spawn(move || {
-            LeaderHandler::run(
-                leader_receiver,
-                peer_handler_sender,
-                output_sender,
-                leader_election_notify,
-                own_id,
-            )
-            .unwrap();
+            processor
+                .run(leader_receiver, leader_election_notify)
+                .unwrap();
         }));
         LeaderHandler { thread_handle }
     }
-
-    fn run(
-        message_receiver: Receiver<(LeaderMessage, PeerIdType)>,
-        peer_handler_sender: Sender<ClientEvent>,
-        output_sender: Sender<ClientMessage>,
-        leader_election_notify: Arc<(Mutex<bool>, Condvar)>,
-        own_id: u32,
-    ) -> io::Result<()> {
-        let mut processor = LeaderProcessor::new(own_id, peer_handler_sender, output_sender);
-        processor.run(message_receiver, leader_election_notify)
-    }
 }
 
 impl LeaderProcessor {
     pub fn new(
         own_id: u32,
+        election_timeout: Duration,
         peer_handler_sender: Sender<ClientEvent>,
         output_sender: Sender<ClientMessage>,
+        term_store: Option<TermStore>,
+        term: TermType,
     ) -> Self {
         LeaderProcessor {
             current_leader: 0,
+            leader_term: 0,
+            term,
+            term_store,
             peer_handler_sender,
             output_sender,
             own_id,
+            election_timeout,
             waiting_coordinator: false,
             election_in_progress: false,
             election_by_user: false,
@@ -75,7 +84,7 @@ impl LeaderProcessor {
 
     fn notify_victory(&self) {
         info!("Victory!");
-        let message = Message::Leader(LeaderMessage::VictoryMessage);
+        let message = Message::Leader(LeaderMessage::VictoryMessage { term: self.term });
         self.peer_handler_sender
             .send(ClientEvent::PeerMessage {
                 message,
@@ -90,7 +99,7 @@ impl LeaderProcessor {
         leader_election_notify: Arc<(Mutex<bool>, Condvar)>,
     ) -> io::Result<()> {
         loop {
-            match receiver.recv_timeout(LEADER_ELECTION_TIMEOUT) {
+            match receiver.recv_timeout(self.election_timeout) {
                 Ok((message, peer_id)) => {
                     debug!("Leader message from {}: {:?}", peer_id, message);
                     let (mutex, cv) = &*leader_election_notify;
@@ -108,7 +117,7 @@ impl LeaderProcessor {
                         // Ningún mayor me dijo Ok
                         if !self.waiting_coordinator {
                             self.notify_victory();
-                            self.current_leader = self.own_id;
+                            self.set_leader(self.own_id, self.term);
                             if self.election_by_user {
                                 self.output_sender
                                     .send(ClientMessage::LeaderElectionFinished)
@@ -144,17 +153,44 @@ impl LeaderProcessor {
     ) {
         match message {
             // Un proceso de pid menor quiere ser lider
-            LeaderMessage::LeaderElectionRequest { .. } => {
-                self.run_election(message, peer_id);
+            LeaderMessage::LeaderElectionRequest { term } => {
+                if term < self.term {
+                    // Quien pide no sabe de la época actual: se abre una nueva para que se entere
+                    info!(
+                        "Election request from {} for stale term {} (current {})",
+                        peer_id, term, self.term
+                    );
+                    self.new_term();
+                } else {
+                    self.observe_term(term);
+                }
+                self.run_election(peer_id);
                 **leader_busy = true;
             }
             // Alguien de pid mayor me dijo "Ok", así que espero el victory
-            LeaderMessage::OkMessage => self.waiting_coordinator = true,
+            LeaderMessage::OkMessage { term } => {
+                if term < self.term {
+                    debug!("Ignoring Ok from {} for stale term {}", peer_id, term);
+                } else {
+                    self.observe_term(term);
+                    self.waiting_coordinator = true;
+                }
+            }
             // Alguien de pid mayor salió lider electo democráticamente, todos amamos al lider
-            LeaderMessage::VictoryMessage {} => {
+            LeaderMessage::VictoryMessage { term } => {
+                // Un coordinator demorado de una elección anterior no pisa al líder actual
+                let stale =
+                    term < self.term || (term == self.leader_term && peer_id < self.current_leader);
+                if stale {
+                    warn!(
+                        "Ignoring coordinator from {} for term {} (leader {} of term {})",
+                        peer_id, term, self.current_leader, self.leader_term
+                    );
+                    return;
+                }
                 info!(
-                    "new leader: {}, initiated by me: {}",
-                    peer_id, self.election_by_user
+                    "new leader: {} for term {}, initiated by me: {}",
+                    peer_id, term, self.election_by_user
                 );
                 if self.election_by_user {
                     self.output_sender
@@ -162,60 +198,95 @@ impl LeaderProcessor {
                         .unwrap();
                     self.election_by_user = false;
                 }
-                self.current_leader = peer_id;
+                self.observe_term(term);
+                self.set_leader(peer_id, term);
                 self.waiting_coordinator = false;
+                self.election_in_progress = false;
             }
             LeaderMessage::CurrentLeaderLocal { response_sender } => {
                 debug!("Current leader: {}", self.current_leader);
                 response_sender.send(self.current_leader).unwrap();
             }
+            LeaderMessage::CurrentTermLocal { response_sender } => {
+                response_sender.send(self.leader_term).unwrap();
+            }
+            LeaderMessage::SeenTermLocal { response_sender } => {
+                response_sender.send(self.term).unwrap();
+            }
             LeaderMessage::PeerDisconnected => {
                 if peer_id == self.current_leader && self.own_id != peer_id {
-                    self.run_election(message, peer_id);
+                    self.new_term();
+                    self.run_election(peer_id);
                 }
             }
             LeaderMessage::SendWelcome => {
                 if self.current_leader == self.own_id {
                     self.peer_handler_sender
                         .send(ClientEvent::PeerMessage {
-                            message: Message::Leader(LeaderMessage::VictoryMessage),
+                            message: Message::Leader(LeaderMessage::VictoryMessage {
+                                term: self.leader_term,
+                            }),
                             peer_id,
                         })
                         .ok();
                 }
             }
-            LeaderMessage::BroadcastBlockchain { blockchain } => {
+            LeaderMessage::BroadcastBlock { block } => {
                 self.peer_handler_sender
                     .send(ClientEvent::PeerMessage {
                         peer_id: self.own_id,
-                        message: Message::Common(ClientMessage::BroadcastBlockchain { blockchain }),
+                        message: Message::Common(ClientMessage::AppendBlock { block }),
                     })
                     .ok();
             }
         }
     }
 
-    fn run_election(&mut self, _message: LeaderMessage, peer_id: PeerIdType) {
+    // La época del líder queda registrada en los bloques que commitea
+    fn set_leader(&mut self, leader: PeerIdType, term: TermType) {
+        self.current_leader = leader;
+        self.leader_term = term;
+    }
+
+    /// Abre una elección en una época nueva
+    fn new_term(&mut self) {
+        self.term += 1;
+        self.save_term();
+    }
+
+    /// Adopta la época de otro nodo si es más nueva que la propia
+    fn observe_term(&mut self, term: TermType) {
+        if term > self.term {
+            self.term = term;
+            self.save_term();
+        }
+    }
+
+    fn save_term(&self) {
+        if let Some(store) = &self.term_store {
+            if let Err(err) = store.save(self.term) {
+                error!("Could not persist term {}: {}", self.term, err);
+            }
+        }
+    }
+
+    fn run_election(&mut self, peer_id: PeerIdType) {
         self.election_in_progress = true;
         // Viene desde un comando de usuario
         info!("Election by {}", peer_id);
         if peer_id == 0 {
-            let message = Message::Leader(LeaderMessage::LeaderElectionRequest {
-                timestamp: SystemTime::now(),
-            });
+            let message = Message::Leader(LeaderMessage::LeaderElectionRequest { term: self.term });
             self.election_by_user = true;
             self.peer_handler_sender
                 .send(ClientEvent::PeerMessage { message, peer_id })
                 .ok();
         } else {
-            let message = Message::Leader(LeaderMessage::OkMessage);
+            let message = Message::Leader(LeaderMessage::OkMessage { term: self.term });
             debug!("Mando a peer {:?}", message);
             self.peer_handler_sender
                 .send(ClientEvent::PeerMessage { message, peer_id })
                 .ok();
-            let message = Message::Leader(LeaderMessage::LeaderElectionRequest {
-                timestamp: SystemTime::now(),
-            });
+            let message = Message::Leader(LeaderMessage::LeaderElectionRequest { term: self.term });
             debug!("Mando LE");
             self.peer_handler_sender
                 .send(ClientEvent::PeerMessage { message, peer_id })
@@ -229,3 +300,98 @@ impl Drop for LeaderHandler {
         let _ = self.thread_handle.take().unwrap().join();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    fn processor(
+        own_id: u32,
+        term_store: Option<TermStore>,
+    ) -> (LeaderProcessor, Receiver<ClientEvent>) {
+        let (peer_sender, peer_receiver) = channel();
+        let (output_sender, _) = channel();
+        let timeout = Duration::from_secs(5);
+        let processor =
+            LeaderProcessor::new(own_id, timeout, peer_sender, output_sender, term_store, 0);
+        (processor, peer_receiver)
+    }
+
+    fn process(processor: &mut LeaderProcessor, message: LeaderMessage, peer_id: PeerIdType) {
+        let busy = Mutex::new(false);
+        processor.process_message(message, peer_id, &mut busy.lock().unwrap());
+    }
+
+    fn sent_terms(receiver: &Receiver<ClientEvent>) -> Vec<String> {
+        receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                ClientEvent::PeerMessage {
+                    message: Message::Leader(message),
+                    ..
+                } => Some(message.serialize().trim_end().to_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stale_coordinator_does_not_replace_the_leader() {
+        let (mut node, _) = processor(1, None);
+        process(&mut node, LeaderMessage::VictoryMessage { term: 2 }, 3);
+        assert_eq!((node.current_leader, node.leader_term), (3, 2));
+
+        // Coordinator demorado de una elección anterior
+        process(&mut node, LeaderMessage::VictoryMessage { term: 1 }, 5);
+        // Misma época pero de un pid menor
+        process(&mut node, LeaderMessage::VictoryMessage { term: 2 }, 2);
+        assert_eq!((node.current_leader, node.leader_term), (3, 2));
+
+        process(&mut node, LeaderMessage::VictoryMessage { term: 3 }, 2);
+        assert_eq!((node.current_leader, node.leader_term), (2, 3));
+    }
+
+    #[test]
+    fn elections_carry_the_highest_term() {
+        let (mut node, sent) = processor(4, None);
+        process(
+            &mut node,
+            LeaderMessage::LeaderElectionRequest { term: 5 },
+            2,
+        );
+        assert_eq!(sent_terms(&sent), ["ok 5", "le 5"]);
+
+        process(&mut node, LeaderMessage::OkMessage { term: 4 }, 6);
+        assert!(!node.waiting_coordinator);
+
+        // Un pedido de una época vieja abre una nueva
+        process(
+            &mut node,
+            LeaderMessage::LeaderElectionRequest { term: 3 },
+            1,
+        );
+        assert_eq!(sent_terms(&sent), ["ok 6", "le 6"]);
+
+        // Se cae el líder: elección en la época siguiente
+        process(&mut node, LeaderMessage::VictoryMessage { term: 6 }, 7);
+        process(&mut node, LeaderMessage::PeerDisconnected, 7);
+        assert_eq!(sent_terms(&sent), ["ok 7", "le 7"]);
+    }
+
+    #[test]
+    fn term_is_persisted() {
+        let dir = std::env::temp_dir().join(format!("leader_term_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (store, _) = TermStore::open(&dir).unwrap();
+        let (mut node, _) = processor(1, Some(store));
+        process(
+            &mut node,
+            LeaderMessage::LeaderElectionRequest { term: 9 },
+            3,
+        );
+        process(&mut node, LeaderMessage::OkMessage { term: 10 }, 3);
+        assert_eq!(TermStore::open(&dir).unwrap().1, 10);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 use crate::blockchain::lock::{CentralizedLock, Lock, LockResult};
 use crate::blockchain::peer::PeerIdType;
-use crate::communication::client_event::{ClientEvent, ClientMessage, Message, LockMessage};
+use crate::communication::client_event::{
+    ClientEvent, ClientMessage, LockMessage, Message, RequestId,
+};
 use std::ops::Deref;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Condvar, Mutex};
@@ -24,12 +26,12 @@ impl LockProcessor {
 
     pub fn handle(&self, message: LockMessage, peer_id: PeerIdType) {
         match message {
-            LockMessage::Acquire => self.acquire(peer_id),
+            LockMessage::Acquire { request_id } => self.acquire(peer_id, request_id),
             LockMessage::Release => self.release(peer_id),
         }
     }
 
-    pub fn acquire(&self, peer_id: PeerIdType) {
+    pub fn acquire(&self, peer_id: PeerIdType, request_id: RequestId) {
         debug!("[{}] Acquiring lock", peer_id);
         let (mutex, cv) = self.lock_notify.deref();
         let acquired;
@@ -44,7 +46,10 @@ impl LockProcessor {
         } else {
             acquired = false
         }
-        let message = Message::Common(ClientMessage::LockResponse(acquired));
+        let message = Message::Common(ClientMessage::LockResponse {
+            request_id,
+            acquired,
+        });
         self.peer_handler_sender
             .send(ClientEvent::PeerMessage { message, peer_id })
             .ok();
@@ -4,12 +4,15 @@ use std::sync::mpsc::{channel, Receiver};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 
-use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::blockchain::{Block, Blockchain, ChainError, Reconciliation, TermType};
+use crate::blockchain::hash::BlockHash;
 use crate::blockchain::peer::PeerIdType;
+use crate::blockchain::storage::ChainStore;
 use crate::communication::client_event::{
     ClientEvent, ClientMessage, ErrorMessage, LeaderMessage, Message,
 };
 use crate::communication::dispatcher::Dispatcher;
+use crate::communication::handshake::ChainTip;
 
 #[derive(Debug)]
 pub struct MessageHandler {
@@ -22,20 +25,24 @@ impl MessageHandler {
         message_receiver: Receiver<(ClientMessage, PeerIdType)>,
         dispatcher: Dispatcher,
         leader_notify: Arc<(Mutex<bool>, Condvar)>,
+        blockchain: Blockchain,
+        store: Option<ChainStore>,
+        tip: Arc<Mutex<ChainTip>>,
     ) -> Self {
         let thread_handle = Some(thread::spawn(move || {
-            MessageHandler::run(own_id, message_receiver, dispatcher, leader_notify).unwrap();
+            let processor =
+                MessageProcessor::new(own_id, dispatcher.clone(), blockchain, store, tip);
+            MessageHandler::run(processor, message_receiver, dispatcher, leader_notify).unwrap();
         }));
         MessageHandler { thread_handle }
     }
 
     fn run(
-        own_id: PeerIdType,
+        mut processor: MessageProcessor,
         message_receiver: Receiver<(ClientMessage, PeerIdType)>,
         dispatcher: Dispatcher,
         leader_notify: Arc<(Mutex<bool>, Condvar)>,
     ) -> io::Result<()> {
-        let mut processor = MessageProcessor::new(own_id, dispatcher.clone());
         for (message, peer_id) in message_receiver {
             MessageHandler::wait_leader_election(&leader_notify);
             if let Some(response) = processor.process_message(message, peer_id) {
@@ -47,10 +54,7 @@ impl MessageHandler {
                         peer_id,
                     })
                     .map_err(|_| {
-                        io::Error::new(
-                            io::ErrorKind::Other,
-                            "[Process message] Error while sending message to peer",
-                        )
+                        io::Error::other("[Process message] Error while sending message to peer")
                     })?;
             }
         }
@@ -68,18 +72,55 @@ impl MessageHandler {
     }
 }
 
+/// Bloques que se piden por mensaje al sincronizar, para no armar líneas de tamaño arbitrario
+const SYNC_CHUNK_SIZE: usize = 64;
+
+/// Bloques de una rama divergente que se guardan como máximo mientras se la recibe. Una rama
+/// más larga no se adopta: se conserva la cadena local.
+const MAX_FORK_BLOCKS: usize = 64 * SYNC_CHUNK_SIZE;
+
+/// Rama de otro nodo que diverge de la local en `from`, recibida tramo a tramo hasta su
+/// punta. Los bloques locales no se tocan hasta tenerla completa y verificada, así que si el
+/// par se cae a mitad de camino la cadena queda como estaba.
+struct PendingFork {
+    peer: PeerIdType,
+    from: usize,
+    /// Hash local del bloque `from - 1`, para notar si la cadena cambió mientras tanto
+    base: BlockHash,
+    blocks: Vec<Block>,
+}
+
+impl PendingFork {
+    /// Altura del próximo bloque de la rama
+    fn next(&self) -> usize {
+        self.from + self.blocks.len()
+    }
+}
+
 struct MessageProcessor {
     id: PeerIdType,
     blockchain: Blockchain,
+    store: Option<ChainStore>,
+    tip: Arc<Mutex<ChainTip>>,
     dispatcher: Dispatcher,
+    pending_fork: Option<PendingFork>,
 }
 
 impl MessageProcessor {
-    pub fn new(own_id: PeerIdType, dispatcher: Dispatcher) -> Self {
+    pub fn new(
+        own_id: PeerIdType,
+        dispatcher: Dispatcher,
+        blockchain: Blockchain,
+        store: Option<ChainStore>,
+        tip: Arc<Mutex<ChainTip>>,
+    ) -> Self {
         MessageProcessor {
             id: own_id,
-            blockchain: Blockchain::new(),
+            blockchain,
+            store,
+            tip,
             dispatcher,
+            pending_fork: None,
         }
     }
 
@@ -91,43 +132,87 @@ impl MessageProcessor {
         let redirect = message.clone();
         debug!("Processing: {:?}", message);
         match message {
-            ClientMessage::ReadBlockchainRequest {} => {
+            ClientMessage::ReadBlockchainRequest { request_id } => {
                 Some(ClientMessage::ReadBlockchainResponse {
+                    request_id,
                     blockchain: self.blockchain.clone(),
                 })
             }
-            ClientMessage::ReadBlockchainResponse { blockchain } => {
-                self.blockchain = blockchain;
-                self.dispatcher.output_sender.send(redirect).ok()?;
+            ClientMessage::ReadBlockchainResponse {
+                request_id,
+                blockchain,
+            } => {
+                let seen_term = self.retrieve_seen_term();
+                let result = self.blockchain.reconcile(blockchain, seen_term);
+                let response = if self.report(result, peer_id) {
+                    ClientMessage::ReadBlockchainResponse {
+                        request_id,
+                        blockchain: self.blockchain.clone(),
+                    }
+                } else {
+                    ClientMessage::ErrorResponse {
+                        request_id: Some(request_id),
+                        error: ErrorMessage::InvalidBlockchainError,
+                    }
+                };
+                self.dispatcher.output_sender.send(response).ok()?;
                 None
             }
-            ClientMessage::WriteBlockchainRequest { transaction } => {
+            ClientMessage::WriteBlockchainRequest {
+                request_id,
+                transactions,
+            } => {
+                let error_response = |error| ClientMessage::ErrorResponse {
+                    request_id: Some(request_id),
+                    error,
+                };
                 let owned = self.is_lock_owned_by(peer_id);
                 if !owned {
-                    return Some(ClientMessage::ErrorResponse(
-                        ErrorMessage::LockNotAcquiredError,
-                    ));
+                    return Some(error_response(ErrorMessage::LockNotAcquiredError));
                 }
                 if self.is_leader() {
-                    let _valid = self.blockchain.validate(&transaction);
-                    self.blockchain.add_transaction(transaction.clone());
+                    if let Err(error) = self.blockchain.validate(&transactions) {
+                        warn!("Rejected write from {}: {}", peer_id, error);
+                        return Some(error_response(ErrorMessage::RejectedTransactionError(
+                            error,
+                        )));
+                    }
+                    let term = self.retrieve_term();
+                    self.blockchain
+                        .add_transactions(transactions.clone(), peer_id, term);
+                    self.persist();
+                    let block = self.blockchain.get_last()?.clone();
                     self.dispatcher
                         .leader_sender
-                        .send((
-                            LeaderMessage::BroadcastBlockchain {
-                                blockchain: self.blockchain.clone(),
-                            },
-                            self.id,
-                        ))
+                        .send((LeaderMessage::BroadcastBlock { block }, self.id))
                         .ok()?;
-                    Some(ClientMessage::WriteBlockchainResponse { transaction })
+                    Some(ClientMessage::WriteBlockchainResponse {
+                        request_id,
+                        term,
+                        transactions,
+                    })
                 } else {
-                    Some(ClientMessage::ErrorResponse(ErrorMessage::NotLeaderError))
+                    Some(error_response(ErrorMessage::NotLeaderError))
                 }
             }
-            ClientMessage::WriteBlockchainResponse { transaction } => {
-                self.blockchain.add_transaction(transaction);
-                self.dispatcher.output_sender.send(redirect).ok()?;
+            ClientMessage::WriteBlockchainResponse {
+                request_id, term, ..
+            } => {
+                // Un líder depuesto pudo commitear después de la elección; se reintenta
+                let current = self.retrieve_term();
+                let response = if term < current {
+                    warn!(
+                        "Write response from stale term {} (current {})",
+                        term, current
+                    );
+                    ClientMessage::ErrorResponse {
+                        request_id: Some(request_id),
+                        error: ErrorMessage::NotLeaderError,
+                    }
+                } else {
+                    redirect
+                };
+                self.dispatcher.output_sender.send(response).ok()?;
                 None
             }
             ClientMessage::LockResponse { .. } => {
@@ -135,17 +220,257 @@ impl MessageProcessor {
                 self.dispatcher.output_sender.send(message).ok()?;
                 None
             }
-            ClientMessage::ErrorResponse(..) => {
+            ClientMessage::ErrorResponse { .. } => {
                 self.dispatcher.output_sender.send(message).ok()?;
                 None
             }
-            ClientMessage::LeaderElectionFinished {} => {
+            ClientMessage::LeaderElectionFinished => {
                 self.dispatcher.output_sender.send(message).ok()?;
                 None
             }
-            ClientMessage::BroadcastBlockchain { blockchain } => {
-                self.blockchain = blockchain;
-                None
+            ClientMessage::AppendBlock { block } => self.append_block(block, peer_id),
+            ClientMessage::BlocksRequest { from } => {
+                let local = self.blockchain.blocks();
+                let to = local.len().min(from.saturating_add(SYNC_CHUNK_SIZE));
+                Some(ClientMessage::BlocksResponse {
+                    from,
+                    height: local.len(),
+                    blocks: local.get(from..to).unwrap_or(&[]).to_vec(),
+                })
+            }
+            ClientMessage::BlocksResponse {
+                from,
+                height,
+                blocks,
+            } => self.apply_blocks(from, height, blocks, peer_id),
+            ClientMessage::SyncStatusRequest => Some(ClientMessage::SyncStatus {
+                height: self.blockchain.blocks().len(),
+                tip: self
+                    .blockchain
+                    .previous_of(self.blockchain.blocks().len())?,
+            }),
+            ClientMessage::SyncStatus { height, tip } => self.sync_with(height, tip, peer_id),
+            // El dispatcher se los pasa al hilo de pares y los avisos de conexión no salen del par
+            ClientMessage::KnownPeers { .. }
+            | ClientMessage::Heartbeat
+            | ClientMessage::DuplicateConnection => None,
+        }
+    }
+
+    /// Aplica un bloque anunciado por el líder si se encadena con el último local; si no,
+    /// pide los bloques que faltan desde donde las cadenas pueden diferir
+    fn append_block(&mut self, block: Block, peer_id: PeerIdType) -> Option<ClientMessage> {
+        let height = block.header().height() as usize;
+        let local = self.blockchain.blocks();
+        if local.get(height).map(Block::hash) == Some(block.hash()) {
+            return None;
+        }
+        let from = height.min(local.len());
+        if height == local.len() {
+            match self.blockchain.append(block) {
+                Ok(()) => {
+                    self.persist();
+                    return None;
+                }
+                Err(err) => warn!("Block {} from {} does not apply: {}", height, peer_id, err),
+            }
+        }
+        info!("Requesting blocks from {} starting at {}", peer_id, from);
+        Some(ClientMessage::BlocksRequest { from })
+    }
+
+    /// Incorpora un tramo de bloques `from..` de una cadena de `height` bloques y pide el
+    /// siguiente tramo si faltan. Cada tramo se verifica antes de aplicarlo. Si no se encadena
+    /// con el bloque `from - 1` local la divergencia es anterior y se retrocede un tramo; una
+    /// rama divergente se recorre hasta su punta antes de aplicar la regla de forks.
+    fn apply_blocks(
+        &mut self,
+        from: usize,
+        height: usize,
+        blocks: Vec<Block>,
+        peer_id: PeerIdType,
+    ) -> Option<ClientMessage> {
+        let next = match from.checked_add(blocks.len()) {
+            Some(next) => next,
+            None => {
+                error!(
+                    "Rejected blocks from {}: invalid range at {}",
+                    peer_id, from
+                );
+                return None;
+            }
+        };
+        let more = next < height && !blocks.is_empty();
+        if let Some(pending) = self.pending_fork.take() {
+            if pending.peer == peer_id && pending.next() == from {
+                return self.continue_fork(pending, blocks, more);
+            }
+            debug!(
+                "Blocks from {} at {} are not part of the fork with {}",
+                peer_id, from, pending.peer
+            );
+            self.pending_fork = Some(pending);
+        }
+
+        let len = self.blockchain.blocks().len();
+        if from > len {
+            return Some(ClientMessage::BlocksRequest { from: len });
+        }
+        let previous = self.blockchain.previous_of(from)?;
+        match Blockchain::verify_segment(from, &previous, &blocks) {
+            Err(ChainError::BrokenLink { height }) if height == from && from > 0 => {
+                let from = from.saturating_sub(SYNC_CHUNK_SIZE);
+                info!(
+                    "Blocks from {} diverge earlier, requesting from {}",
+                    peer_id, from
+                );
+                return Some(ClientMessage::BlocksRequest { from });
+            }
+            Err(err) => {
+                error!("Rejected blocks from {}: {}", peer_id, err);
+                return None;
+            }
+            Ok(()) => {}
+        }
+        let matching = self.blockchain.matching(from, &blocks);
+        let forked = from + matching < len && matching < blocks.len();
+        if forked && more {
+            let from = from + matching;
+            info!(
+                "Fork with {} after block {}, fetching their branch",
+                peer_id, from
+            );
+            if let Some(abandoned) = &self.pending_fork {
+                warn!("Abandoning the fork with {}", abandoned.peer);
+            }
+            self.pending_fork = Some(PendingFork {
+                peer: peer_id,
+                from,
+                base: self.blockchain.previous_of(from)?,
+                blocks: blocks.into_iter().skip(matching).collect(),
+            });
+            return Some(ClientMessage::BlocksRequest { from: next });
+        }
+        let seen_term = self.retrieve_seen_term();
+        let result = self.blockchain.reconcile_segment(from, blocks, seen_term);
+        if self.report(result, peer_id) && more {
+            return Some(ClientMessage::BlocksRequest { from: next });
+        }
+        None
+    }
+
+    /// Verifica y guarda el siguiente tramo de una rama divergente y, al llegar a su punta,
+    /// aplica la regla de forks. Si gana la rama reemplaza a los bloques locales de una vez.
+    fn continue_fork(
+        &mut self,
+        mut pending: PendingFork,
+        blocks: Vec<Block>,
+        more: bool,
+    ) -> Option<ClientMessage> {
+        let peer_id = pending.peer;
+        let last_hash = *pending.blocks.last()?.hash();
+        if let Err(err) = Blockchain::verify_segment(pending.next(), &last_hash, &blocks) {
+            error!("Rejected blocks from {}: {}", peer_id, err);
+            return None;
+        }
+        if pending.blocks.len() + blocks.len() > MAX_FORK_BLOCKS {
+            error!(
+                "Fork with {} at block {}: their branch is longer than {} blocks, keeping ours",
+                peer_id, pending.from, MAX_FORK_BLOCKS
+            );
+            return None;
+        }
+        pending.blocks.extend(blocks);
+        if more {
+            let from = pending.next();
+            self.pending_fork = Some(pending);
+            return Some(ClientMessage::BlocksRequest { from });
+        }
+        if self.blockchain.previous_of(pending.from) != Some(pending.base) {
+            warn!(
+                "Chain changed while fetching the fork with {}, syncing again",
+                peer_id
+            );
+            return Some(ClientMessage::SyncStatusRequest);
+        }
+        let seen_term = self.retrieve_seen_term();
+        let result = self
+            .blockchain
+            .reconcile_segment(pending.from, pending.blocks, seen_term);
+        self.report(result, peer_id);
+        None
+    }
+
+    /// Pide los bloques que faltan si el otro nodo anuncia una cadena que no es prefijo de
+    /// la local
+    fn sync_with(
+        &self,
+        height: usize,
+        tip: BlockHash,
+        peer_id: PeerIdType,
+    ) -> Option<ClientMessage> {
+        let local = self.blockchain.blocks();
+        let known = height
+            .checked_sub(1)
+            .is_none_or(|last| local.get(last).map(Block::hash) == Some(&tip));
+        if known {
+            return None;
+        }
+        let from = local.len().min(height.saturating_sub(1));
+        info!(
+            "Peer {} has {} blocks, syncing from {}",
+            peer_id, height, from
+        );
+        Some(ClientMessage::BlocksRequest { from })
+    }
+
+    /// Registra el resultado de reconciliar con lo recibido y devuelve si era válido
+    fn report(&mut self, result: Result<Reconciliation, ChainError>, peer_id: PeerIdType) -> bool {
+        match result {
+            Ok(Reconciliation::Stale) => {
+                debug!("Blockchain from {} has nothing new", peer_id);
+            }
+            Ok(Reconciliation::Extended { added }) => {
+                debug!("Blockchain from {} adds {} blocks", peer_id, added);
+                self.persist();
+            }
+            Ok(Reconciliation::Fork {
+                ancestor,
+                adopted,
+                discarded,
+            }) => {
+                let ancestor = ancestor.map_or("none".to_owned(), |height| height.to_string());
+                let winner = if adopted { "theirs" } else { "ours" };
+                error!(
+                    "Fork with {} after block {}: keeping {}, discarding {} transactions",
+                    peer_id,
+                    ancestor,
+                    winner,
+                    discarded.len()
+                );
+                for transaction in discarded {
+                    error!("Discarded by fork: {}", transaction);
+                }
+                if adopted {
+                    self.persist();
+                }
+            }
+            Err(err) => {
+                error!("Rejected blockchain from {}: {}", peer_id, err);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Se llama cada vez que cambia la cadena: la guarda y actualiza la punta anunciada
+    fn persist(&mut self) {
+        if let Ok(mut tip) = self.tip.lock() {
+            *tip = ChainTip::of(&self.blockchain);
+        }
+        if let Some(store) = &mut self.store {
+            if let Err(err) = store.sync(&self.blockchain) {
+                error!("Could not persist blockchain: {}", err);
             }
         }
     }
@@ -164,6 +489,21 @@ impl MessageProcessor {
         self.dispatcher.leader_sender.send((message, 0)).unwrap();
         response_receiver.recv().unwrap()
     }
+
+    fn retrieve_term(&self) -> TermType {
+        let (response_sender, response_receiver) = channel();
+        let message = LeaderMessage::CurrentTermLocal { response_sender };
+        self.dispatcher.leader_sender.send((message, 0)).unwrap();
+        response_receiver.recv().unwrap()
+    }
+
+    /// Término más alto visto en las elecciones, aunque todavía no haya líder para él
+    fn retrieve_seen_term(&self) -> TermType {
+        let (response_sender, response_receiver) = channel();
+        let message = LeaderMessage::SeenTermLocal { response_sender };
+        self.dispatcher.leader_sender.send((message, 0)).unwrap();
+        response_receiver.recv().unwrap()
+    }
 }
 
 impl Drop for MessageHandler {
@@ -171,3 +511,250 @@ impl Drop for MessageHandler {
         let _ = self.thread_handle.take().unwrap().join();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::blockchain::{Transaction, TransactionData};
+    use crate::blockchain::lock::CentralizedLock;
+    use crate::handler::lock_handler::LockProcessor;
+    use std::sync::mpsc::Sender;
+    use std::time::Duration;
+
+    /// Todos los nodos de las pruebas vieron elecciones hasta el término 2
+    fn processor(id: PeerIdType, blockchain: Blockchain) -> MessageProcessor {
+        processor_with(id, blockchain, leader_in_term(2), channel().0)
+    }
+
+    /// Hilo de elección que contesta `term` a las consultas de término
+    fn leader_in_term(term: TermType) -> Sender<(LeaderMessage, PeerIdType)> {
+        let (leader_sender, leader_receiver) = channel();
+        thread::spawn(move || {
+            for (message, _) in leader_receiver {
+                match message {
+                    LeaderMessage::CurrentTermLocal { response_sender }
+                    | LeaderMessage::SeenTermLocal { response_sender } => {
+                        response_sender.send(term).unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        });
+        leader_sender
+    }
+
+    fn processor_with(
+        id: PeerIdType,
+        blockchain: Blockchain,
+        leader_sender: Sender<(LeaderMessage, PeerIdType)>,
+        output_sender: Sender<ClientMessage>,
+    ) -> MessageProcessor {
+        let (peer_sender, _) = channel();
+        let (message_sender, _) = channel();
+        let lock = Arc::new((
+            Mutex::new(CentralizedLock::new(Duration::from_secs(5))),
+            Condvar::new(),
+        ));
+        let lock_handler = LockProcessor::new(peer_sender.clone(), lock);
+        let dispatcher = Dispatcher::new(
+            id,
+            peer_sender,
+            message_sender,
+            leader_sender,
+            output_sender,
+            lock_handler,
+        );
+        let tip = Arc::new(Mutex::new(ChainTip::of(&blockchain)));
+        MessageProcessor::new(id, dispatcher, blockchain, None, tip)
+    }
+
+    fn chain(base: &Blockchain, student: &str, term: u64, blocks: usize) -> Blockchain {
+        let mut chain = base.clone();
+        for i in 0..blocks {
+            let data = TransactionData::new(student, "algo1", &format!("tp{}", i), 7);
+            chain.add_transactions(vec![Transaction::Insert(data)], 1, term);
+        }
+        chain
+    }
+
+    /// Intercambia mensajes entre los dos nodos hasta que ninguno responde
+    fn sync(follower: &mut MessageProcessor, leader: &mut MessageProcessor) -> usize {
+        let mut request = leader.process_message(ClientMessage::SyncStatusRequest, 1);
+        let mut chunks = 0;
+        while let Some(message) = request.take() {
+            let reply = follower.process_message(message, 2);
+            request = reply.and_then(|reply| {
+                let response = leader.process_message(reply, 1)?;
+                if let ClientMessage::BlocksResponse { blocks, .. } = &response {
+                    assert!(blocks.len() <= SYNC_CHUNK_SIZE);
+                    chunks += 1;
+                }
+                Some(response)
+            });
+        }
+        chunks
+    }
+
+    #[test]
+    fn lagging_node_catches_up_in_chunks() {
+        let base = chain(&Blockchain::new(), "pedro", 1, 10);
+        let mut leader = processor(2, chain(&base, "ana", 1, 3 * SYNC_CHUNK_SIZE));
+        let mut follower = processor(1, base);
+        let chunks = sync(&mut follower, &mut leader);
+        assert_eq!(chunks, 3);
+        assert_eq!(follower.blockchain.blocks(), leader.blockchain.blocks());
+    }
+
+    #[test]
+    fn diverged_node_walks_back_and_adopts_the_winning_branch() {
+        let base = chain(&Blockchain::new(), "pedro", 1, 2 * SYNC_CHUNK_SIZE);
+        let mut leader = processor(2, chain(&base, "ana", 2, 2 * SYNC_CHUNK_SIZE));
+        let mut follower = processor(1, chain(&base, "luis", 1, 3 * SYNC_CHUNK_SIZE));
+        sync(&mut follower, &mut leader);
+        assert!(follower.pending_fork.is_none());
+        assert_eq!(follower.blockchain.blocks(), leader.blockchain.blocks());
+        assert_eq!(follower.blockchain.state(), leader.blockchain.state());
+    }
+
+    #[test]
+    fn losing_branch_is_walked_without_keeping_it() {
+        let base = chain(&Blockchain::new(), "pedro", 1, 2 * SYNC_CHUNK_SIZE);
+        let mut leader = processor(2, chain(&base, "ana", 1, 2 * SYNC_CHUNK_SIZE));
+        let ours = chain(&base, "luis", 2, SYNC_CHUNK_SIZE);
+        let mut follower = processor(1, ours.clone());
+        let status = leader.process_message(ClientMessage::SyncStatusRequest, 1);
+        let mut request = follower.process_message(status.unwrap(), 2);
+        while follower.pending_fork.is_none() {
+            let response = leader.process_message(request.unwrap(), 1).unwrap();
+            request = follower.process_message(response, 2);
+        }
+
+        // Lo que manda otro nodo mientras tanto no corta el recorrido de la rama
+        let unrelated = ClientMessage::BlocksResponse {
+            from: 0,
+            height: 0,
+            blocks: vec![],
+        };
+        assert!(follower.process_message(unrelated, 3).is_none());
+        assert!(follower.pending_fork.is_some());
+
+        while let Some(message) = request.take() {
+            let response = leader.process_message(message, 1).unwrap();
+            request = follower.process_message(response, 2);
+        }
+        assert!(follower.pending_fork.is_none());
+        assert_eq!(follower.blockchain.blocks(), ours.blocks());
+    }
+
+    #[test]
+    fn local_chain_survives_a_peer_lost_mid_fork() {
+        let dir = std::env::temp_dir().join(format!("message_handler_{}_fork", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let base = chain(&Blockchain::new(), "pedro", 1, SYNC_CHUNK_SIZE);
+        let mut leader = processor(2, chain(&base, "ana", 2, 3 * SYNC_CHUNK_SIZE));
+        let ours = chain(&base, "luis", 1, SYNC_CHUNK_SIZE);
+        let mut follower = processor(1, ours.clone());
+        let (mut store, _) = ChainStore::open(&dir).unwrap();
+        store.sync(&ours).unwrap();
+        follower.store = Some(store);
+
+        let status = leader.process_message(ClientMessage::SyncStatusRequest, 1);
+        let mut request = follower.process_message(status.unwrap(), 2);
+        while follower.pending_fork.is_none() {
+            let response = leader.process_message(request.unwrap(), 1).unwrap();
+            request = follower.process_message(response, 2);
+        }
+        // Llega un tramo más de la rama ganadora y el par deja de contestar
+        let response = leader.process_message(request.unwrap(), 1).unwrap();
+        assert!(follower.process_message(response, 2).is_some());
+        assert_eq!(follower.blockchain.blocks(), ours.blocks());
+        let (_, saved) = ChainStore::open(&dir).unwrap();
+        assert_eq!(saved.blocks(), ours.blocks());
+
+        // Al volver se recorre la rama de nuevo y se la adopta completa
+        sync(&mut follower, &mut leader);
+        assert_eq!(follower.blockchain.blocks(), leader.blockchain.blocks());
+        let (_, saved) = ChainStore::open(&dir).unwrap();
+        assert_eq!(saved.blocks(), leader.blockchain.blocks());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn branch_claiming_an_unseen_term_is_not_adopted() {
+        let base = chain(&Blockchain::new(), "pedro", 1, SYNC_CHUNK_SIZE);
+        let mut forger = processor(2, chain(&base, "ana", 9, 2 * SYNC_CHUNK_SIZE));
+        let ours = chain(&base, "luis", 2, SYNC_CHUNK_SIZE);
+        let mut follower = processor(1, ours.clone());
+        sync(&mut follower, &mut forger);
+        assert!(follower.pending_fork.is_none());
+        assert_eq!(follower.blockchain.blocks(), ours.blocks());
+    }
+
+    #[test]
+    fn overflowing_block_ranges_are_rejected() {
+        let blocks = chain(&Blockchain::new(), "pedro", 1, 2).blocks().to_vec();
+        let mut node = processor(1, Blockchain::new());
+        let response = ClientMessage::BlocksResponse {
+            from: usize::MAX,
+            height: usize::MAX,
+            blocks,
+        };
+        assert!(node.process_message(response, 2).is_none());
+        assert_eq!(node.blockchain.height(), 0);
+    }
+
+    #[test]
+    fn node_ahead_does_not_request_anything() {
+        let base = chain(&Blockchain::new(), "pedro", 1, 5);
+        let mut leader = processor(2, base.clone());
+        let mut follower = processor(1, chain(&base, "ana", 1, 5));
+        assert_eq!(sync(&mut follower, &mut leader), 0);
+        assert_eq!(follower.blockchain.height(), 10);
+    }
+
+    #[test]
+    fn responses_echo_the_request_id() {
+        let mut node = processor(1, Blockchain::new());
+        let response =
+            node.process_message(ClientMessage::ReadBlockchainRequest { request_id: 7 }, 2);
+        assert_eq!(response.unwrap().request_id(), Some(7));
+
+        let data = TransactionData::new("pedro", "algo1", "final", 7);
+        let write = ClientMessage::WriteBlockchainRequest {
+            request_id: 8,
+            transactions: vec![Transaction::Insert(data)],
+        };
+        match node.process_message(write, 2) {
+            Some(ClientMessage::ErrorResponse {
+                request_id: Some(8),
+                error: ErrorMessage::LockNotAcquiredError,
+            }) => {}
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_responses_from_a_stale_term_are_retried() {
+        let (output_sender, output_receiver) = channel();
+        let mut node = processor_with(1, Blockchain::new(), leader_in_term(3), output_sender);
+        for term in [2, 3].iter() {
+            let response = ClientMessage::WriteBlockchainResponse {
+                request_id: *term,
+                term: *term,
+                transactions: vec![],
+            };
+            assert!(node.process_message(response, 2).is_none());
+        }
+        match output_receiver.recv().unwrap() {
+            ClientMessage::ErrorResponse {
+                request_id: Some(2),
+                error: ErrorMessage::NotLeaderError,
+            } => {}
+            other => panic!("unexpected response {:?}", other),
+        }
+        assert!(matches!(
+            output_receiver.recv().unwrap(),
+            ClientMessage::WriteBlockchainResponse { term: 3, .. }
+        ));
+    }
+}
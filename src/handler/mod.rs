@@ -1,4 +1,5 @@
 pub mod connection_handler;
+pub mod discovery_handler;
 pub mod input_handler;
 pub mod leader_handler;
 pub mod lock_handler;
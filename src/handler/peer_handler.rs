@@ -1,58 +1,102 @@
+use crate::blockchain::config::Config;
 use crate::blockchain::peer::{Peer, PeerIdType};
+use crate::blockchain::reconnect::ReconnectManager;
 use crate::communication::client_event::{ClientEvent, ClientMessage, LeaderMessage, Message};
 use crate::communication::dispatcher::Dispatcher;
-use std::collections::HashMap;
+use crate::communication::handshake::{ChainTip, Handshake, HandshakeError};
+use crate::communication::serialization::WireFormat;
+use std::collections::{HashMap, HashSet};
 use std::io;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
-use std::str::FromStr;
-use std::sync::mpsc::Receiver;
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::SystemTime;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct PeerHandler {
+    sender: Sender<ClientEvent>,
     thread_handle: Option<thread::JoinHandle<io::Result<()>>>,
 }
 
 pub struct PeerProcessor {
     connected_peers: HashMap<u32, Peer>,
+    /// Direcciones anunciadas por otros pares a las que se está intentando conectar
+    dialing: HashSet<SocketAddr>,
+    reconnect: ReconnectManager,
     own_id: PeerIdType,
+    listen_addr: SocketAddr,
+    wire_format: WireFormat,
+    /// Opciones con las que se arma cada `Peer`
+    config: Config,
+    /// Tiempo máximo para recibir el handshake de una conexión nueva
+    handshake_timeout: Duration,
+    tip: Arc<Mutex<ChainTip>>,
     receiver: Receiver<ClientEvent>,
     dispatcher: Dispatcher,
 }
 
 impl PeerProcessor {
     pub fn new(
-        connected_peers: HashMap<u32, Peer>,
-        own_id: PeerIdType,
+        config: &Config,
+        listen_addr: SocketAddr,
+        tip: Arc<Mutex<ChainTip>>,
         receiver: Receiver<ClientEvent>,
         dispatcher: Dispatcher,
     ) -> Self {
         Self {
-            connected_peers,
-            own_id,
+            connected_peers: HashMap::new(),
+            dialing: HashSet::new(),
+            reconnect: ReconnectManager::new(
+                config.reconnect_delay(),
+                config.reconnect_max_delay(),
+            ),
+            own_id: config.node_id(),
+            listen_addr,
+            wire_format: config.wire_format(),
+            config: config.clone(),
+            handshake_timeout: config.handshake_timeout(),
+            tip,
             receiver,
             dispatcher,
         }
     }
     pub fn process(&mut self) -> io::Result<()> {
-        for event in self.receiver.iter() {
+        self.dial_initial();
+        while let Ok(event) = self.receiver.recv() {
             debug!("Peer handler: Processing event: {:?}", event);
             match event {
-                ClientEvent::Connection {
-                    mut stream,
+                ClientEvent::Connection { stream, incoming } => {
+                    self.start_handshake(stream, incoming);
+                }
+                ClientEvent::PeerConnected {
+                    stream,
+                    handshake,
                     incoming,
-                } => {
-                    let peer_id = PeerHandler::exchange_pids(self.own_id, &mut stream)?;
-                    if !incoming {
-                        PeerHandler::send_initial_data(&self.dispatcher, peer_id);
+                } => self.add_peer(stream, handshake, incoming),
+                ClientEvent::KnownPeers { peers } => {
+                    for (peer_id, addr) in peers {
+                        self.dial(peer_id, addr);
                     }
-                    let peer = Peer::new(peer_id, stream, self.dispatcher.clone());
-                    self.connected_peers.insert(peer_id, peer);
                 }
+                ClientEvent::DialFailed { addr } => {
+                    self.dialing.remove(&addr);
+                    self.retry(addr);
+                }
+                ClientEvent::Shutdown => break,
                 ClientEvent::PeerDisconnected { peer_id } => {
-                    self.connected_peers.remove(&peer_id);
+                    let replaced = self
+                        .connected_peers
+                        .get(&peer_id)
+                        .is_some_and(Peer::is_connected);
+                    if replaced {
+                        // Se cortó una conexión duplicada; la que quedó sigue viva
+                        debug!("Ignoring closed duplicate connection with {}", peer_id);
+                        continue;
+                    }
+                    if let Some(peer) = self.connected_peers.remove(&peer_id) {
+                        self.reconnect_later(peer_id, peer.listen_addr());
+                    }
                     warn!("Peer {} removed", peer_id);
                     let message = LeaderMessage::PeerDisconnected;
                     self.dispatcher.leader_sender.send((message, peer_id)).ok();
@@ -67,12 +111,222 @@ impl PeerProcessor {
         Ok(())
     }
 
+    /// El handshake corre en otro hilo: si dos nodos se llaman a la vez y cada uno atendiera
+    /// primero la conexión entrante, los dos esperarían un handshake que el otro no mandó
+    fn start_handshake(&mut self, mut stream: TcpStream, incoming: bool) {
+        let dialed = if incoming {
+            None
+        } else {
+            stream.peer_addr().ok()
+        };
+        if let Some(addr) = dialed {
+            self.dialing.remove(&addr);
+        }
+        let tip = *self.tip.lock().unwrap();
+        // Si se escucha en todas las interfaces se anuncia la que usó el otro para llegar
+        let listen_addr = match stream.local_addr() {
+            Ok(local) if self.listen_addr.ip().is_unspecified() => {
+                SocketAddr::new(local.ip(), self.listen_addr.port())
+            }
+            _ => self.listen_addr,
+        };
+        let ours = Handshake::new(self.own_id, listen_addr, self.wire_format, tip);
+        let timeout = self.handshake_timeout;
+        let dispatcher = self.dispatcher.clone();
+        thread::spawn(move || {
+            let event = match PeerHandler::handshake(&ours, &mut stream, timeout) {
+                Ok(handshake) => ClientEvent::PeerConnected {
+                    stream,
+                    handshake,
+                    incoming,
+                },
+                Err(err) => {
+                    warn!(
+                        "Rejected connection from {:?}: {}",
+                        stream.peer_addr().ok(),
+                        err
+                    );
+                    stream.shutdown(Shutdown::Both).ok();
+                    match dialed {
+                        Some(addr) => ClientEvent::DialFailed { addr },
+                        None => return,
+                    }
+                }
+            };
+            dispatcher.dispatch(event).ok();
+        });
+    }
+
+    fn add_peer(&mut self, mut stream: TcpStream, theirs: Handshake, incoming: bool) {
+        let peer_id = theirs.node_id();
+        let format = WireFormat::negotiate(self.wire_format.offered(), &theirs.formats());
+        let dialed = if incoming {
+            None
+        } else {
+            stream.peer_addr().ok()
+        };
+        if let Some(existing) = self.connected_peers.get(&peer_id) {
+            if !self.keeps_new_connection(peer_id, incoming, existing) {
+                info!("Closing duplicate connection with {}", peer_id);
+                Peer::close_duplicate(&mut stream, format);
+                return;
+            }
+        }
+        info!(
+            "Peer {} listening on {} connected using {} format, {} blocks",
+            peer_id,
+            theirs.listen_addr(),
+            format.name(),
+            theirs.tip().height()
+        );
+        let peer = Peer::new(
+            peer_id,
+            theirs.listen_addr(),
+            stream,
+            incoming,
+            format,
+            &self.config,
+            self.dispatcher.clone(),
+        );
+        let peer = match peer {
+            Ok(peer) => peer,
+            Err(err) => {
+                warn!("Could not set up the connection with {}: {}", peer_id, err);
+                return;
+            }
+        };
+        // La conexión vieja se deja recién cuando la nueva quedó andando
+        if let Some(existing) = self.connected_peers.get(&peer_id) {
+            info!("Replacing connection with {}", peer_id);
+            let notice = Message::Common(ClientMessage::DuplicateConnection);
+            existing.send_message(notice).ok();
+        }
+        self.connected_peers.insert(peer_id, peer);
+        self.reconnect.reconnected(peer_id, dialed);
+        PeerHandler::send_initial_data(&self.dispatcher, peer_id, incoming, theirs.tip());
+        self.share_peers(peer_id, theirs.listen_addr());
+    }
+
+    /// Cuando dos nodos se llaman a la vez, entre las dos conexiones se queda la que abrió el
+    /// de id menor; los dos extremos eligen la misma. Si las dos van en el mismo sentido es que
+    /// el par volvió a llamar y la nueva reemplaza a la vieja, igual que si la vieja ya se cortó.
+    fn keeps_new_connection(&self, peer_id: PeerIdType, incoming: bool, existing: &Peer) -> bool {
+        let lower_dialed = incoming == (peer_id < self.own_id);
+        lower_dialed || incoming == existing.is_incoming() || !existing.is_connected()
+    }
+
+    /// Le pasa al par nuevo los demás pares conocidos y se lo anuncia a ellos, así la malla
+    /// completa se forma a partir de cualquier semilla
+    fn share_peers(&self, new_id: PeerIdType, new_addr: SocketAddr) {
+        let mut known = vec![];
+        for (&peer_id, peer) in self.connected_peers.iter() {
+            if peer_id == new_id {
+                continue;
+            }
+            known.push((peer_id, peer.listen_addr()));
+            let announce = ClientMessage::KnownPeers {
+                peers: vec![(new_id, new_addr)],
+            };
+            peer.send_message(Message::Common(announce)).ok();
+        }
+        if let Some(peer) = self.connected_peers.get(&new_id) {
+            let message = ClientMessage::KnownPeers { peers: known };
+            peer.send_message(Message::Common(message)).ok();
+        }
+    }
+
+    /// Al arrancar llama a las semillas configuradas o, si no hay, al resto de los puertos del
+    /// rango en la dirección propia; con descubrimiento por UDP no hace falta barrer los
+    /// puertos. Las semillas que no contestan se siguen intentando, los puertos del rango no.
+    fn dial_initial(&mut self) {
+        let seeds = self.config.seeds().to_vec();
+        for seed in seeds.iter() {
+            match seed.to_socket_addrs().map(|mut addrs| addrs.next()) {
+                Ok(Some(addr)) => {
+                    info!("Connecting to seed {}", seed);
+                    self.reconnect.seed(addr);
+                    self.connect(addr, Duration::ZERO);
+                }
+                _ => warn!("Could not resolve seed {}", seed),
+            }
+        }
+        if !seeds.is_empty() || self.config.discovery_group().is_some() {
+            return;
+        }
+        let host = match self.listen_addr.ip() {
+            ip if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            ip => ip,
+        };
+        let own_port = self.listen_addr.port();
+        for port in self.config.ports().filter(|port| *port != own_port) {
+            self.connect(SocketAddr::new(host, port), Duration::ZERO);
+        }
+    }
+
+    /// Se conecta en otro hilo a un par anunciado. Entre dos nodos que se enteran uno del
+    /// otro sólo llama el de id menor, para no abrir dos conexiones a la vez.
+    fn dial(&mut self, peer_id: PeerIdType, addr: SocketAddr) {
+        let known = peer_id <= self.own_id
+            || self.connected_peers.contains_key(&peer_id)
+            || self.dialing.contains(&addr);
+        if known {
+            return;
+        }
+        info!("Connecting to announced peer {} at {}", peer_id, addr);
+        self.connect(addr, Duration::ZERO);
+    }
+
+    /// Vuelve a llamar a un par caído, con la misma regla que `dial`: si el otro tiene id
+    /// menor es él quien llama
+    fn reconnect_later(&mut self, peer_id: PeerIdType, addr: SocketAddr) {
+        if peer_id <= self.own_id {
+            return;
+        }
+        let delay = self.reconnect.lost(peer_id, addr);
+        info!(
+            "Reconnecting to peer {} at {} in {:?}",
+            peer_id, addr, delay
+        );
+        self.connect(addr, delay);
+    }
+
+    /// Si la dirección es de un par caído o de una semilla programa otro intento, cada vez
+    /// más espaciado
+    fn retry(&mut self, addr: SocketAddr) {
+        if let Some(delay) = self.reconnect.failed(addr) {
+            info!("Retrying {} in {:?}", addr, delay);
+            self.connect(addr, delay);
+        }
+    }
+
+    /// Intenta conectarse desde otro hilo después de la espera; el resultado vuelve como
+    /// `Connection` o `DialFailed`
+    fn connect(&mut self, addr: SocketAddr, delay: Duration) {
+        self.dialing.insert(addr);
+        let dispatcher = self.dispatcher.clone();
+        let timeout = self.handshake_timeout;
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let event = match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => ClientEvent::Connection {
+                    stream,
+                    incoming: false,
+                },
+                Err(err) => {
+                    debug!("Could not connect to {}: {}", addr, err);
+                    ClientEvent::DialFailed { addr }
+                }
+            };
+            dispatcher.dispatch(event).ok();
+        });
+    }
+
     fn handle_peer_message(&self, message: Message, peer_id: PeerIdType) {
         match message {
-            Message::Common(ClientMessage::BroadcastBlockchain { blockchain }) => {
+            Message::Common(ClientMessage::AppendBlock { block }) => {
                 for (_, peer) in self.connected_peers.iter() {
-                    peer.send_message(Message::Common(ClientMessage::ReadBlockchainResponse {
-                        blockchain: blockchain.clone(),
+                    peer.send_message(Message::Common(ClientMessage::AppendBlock {
+                        block: block.clone(),
                     }))
                     .ok();
                 }
@@ -97,19 +351,18 @@ impl PeerProcessor {
                 }
             },
             Message::Leader(message) => match message {
-                LeaderMessage::LeaderElectionRequest { .. } => {
+                LeaderMessage::LeaderElectionRequest { term } => {
                     self.connected_peers
                         .iter()
                         .filter(|(&peer_id, _)| peer_id > self.own_id)
                         .for_each(|(peer_id, peer)| {
                             info!("Pidiendo ser lider a {}", peer_id);
-                            let msg = Message::Leader(LeaderMessage::LeaderElectionRequest {
-                                timestamp: SystemTime::now(),
-                            });
+                            let msg =
+                                Message::Leader(LeaderMessage::LeaderElectionRequest { term });
                             peer.send_message(msg).ok();
                         });
                 }
-                LeaderMessage::OkMessage {} => {
+                LeaderMessage::OkMessage { .. } => {
                     if let Some(peer) = self.connected_peers.get(&peer_id) {
                         let sent = peer.send_message(Message::Leader(message));
                         if sent.is_err() {
@@ -117,10 +370,10 @@ impl PeerProcessor {
                         }
                     }
                 }
-                LeaderMessage::VictoryMessage {} => {
+                LeaderMessage::VictoryMessage { term } => {
                     for (peer_id, peer) in self.connected_peers.iter() {
                         info!("Send victory to {}!", peer_id);
-                        peer.send_message(Message::Leader(LeaderMessage::VictoryMessage {}))
+                        peer.send_message(Message::Leader(LeaderMessage::VictoryMessage { term }))
                             .ok();
                     }
                 }
@@ -140,61 +393,306 @@ impl PeerProcessor {
     }
 }
 impl PeerHandler {
+    fn handshake(
+        ours: &Handshake,
+        stream: &mut TcpStream,
+        timeout: Duration,
+    ) -> Result<Handshake, HandshakeError> {
+        stream.set_read_timeout(Some(timeout))?;
+        let theirs = ours.exchange(stream)?;
+        stream.set_read_timeout(None)?;
+        Ok(theirs)
+    }
+
     pub fn new(
-        own_id: PeerIdType,
+        config: &Config,
+        listen_addr: SocketAddr,
+        tip: Arc<Mutex<ChainTip>>,
         request_receiver: Receiver<ClientEvent>,
         dispatcher: Dispatcher,
     ) -> Self {
-        let connected_peers = HashMap::new();
+        let config = config.clone();
+        let sender = dispatcher.peer_sender.clone();
         let thread_handle = thread::spawn(move || {
-            PeerHandler::run(own_id, request_receiver, dispatcher, connected_peers)
+            let mut processor =
+                PeerProcessor::new(&config, listen_addr, tip, request_receiver, dispatcher);
+            processor.process()
         });
         PeerHandler {
+            sender,
             thread_handle: Some(thread_handle),
         }
     }
 
-    fn run(
-        own_id: PeerIdType,
-        receiver: Receiver<ClientEvent>,
-        dispatcher: Dispatcher,
-        connected_peers: HashMap<u32, Peer>,
-    ) -> io::Result<()> {
-        let mut processor = PeerProcessor::new(connected_peers, own_id, receiver, dispatcher);
-        processor.process()
-    }
-
-    fn exchange_pids(own_id: PeerIdType, stream: &mut TcpStream) -> io::Result<u32> {
-        debug!("Exchanging pids with new connection");
-        let pid_msg = format!("{}\n", own_id);
-        stream.write_all(pid_msg.as_bytes())?;
-        let mut buf_reader = BufReader::new(stream);
-        let mut client_pid = String::new();
-        buf_reader.read_line(&mut client_pid)?;
-        client_pid.pop();
-        debug!("Pid exchanged with {}", client_pid);
-        u32::from_str(&client_pid)
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "bad client pid"))
-    }
-
-    fn send_initial_data(dispatcher: &Dispatcher, peer_id: PeerIdType) {
-        info!("New peer, sending welcome");
-        dispatcher
-            .leader_sender
-            .send((LeaderMessage::SendWelcome {}, peer_id))
-            .ok();
-        dispatcher
-            .peer_sender
-            .send(ClientEvent::PeerMessage {
-                message: Message::Common(ClientMessage::ReadBlockchainRequest),
-                peer_id,
-            })
-            .ok();
+    /// La punta anunciada en el handshake se procesa como un `SyncStatus` del par, así
+    /// cada extremo pide lo que le falta sin otra ida y vuelta
+    fn send_initial_data(
+        dispatcher: &Dispatcher,
+        peer_id: PeerIdType,
+        incoming: bool,
+        tip: ChainTip,
+    ) {
+        if !incoming {
+            info!("New peer, sending welcome");
+            dispatcher
+                .leader_sender
+                .send((LeaderMessage::SendWelcome {}, peer_id))
+                .ok();
+        }
+        let status = ClientMessage::SyncStatus {
+            height: tip.height(),
+            tip: tip.hash(),
+        };
+        dispatcher.message_sender.send((status, peer_id)).ok();
     }
 }
 
 impl Drop for PeerHandler {
     fn drop(&mut self) {
+        // Los hilos de cada par tienen su propio dispatcher, así que el canal no se cierra solo
+        self.sender.send(ClientEvent::Shutdown).ok();
         let _ = self.thread_handle.take().unwrap().join();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::blockchain::Blockchain;
+    use crate::blockchain::lock::CentralizedLock;
+    use crate::handler::lock_handler::LockProcessor;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::sync::Condvar;
+    use std::time::Instant;
+
+    /// Límite para esperas que en condiciones normales terminan enseguida
+    const PATIENCE: Duration = Duration::from_secs(10);
+
+    /// Acepta la próxima conexión, o falla si no llega ninguna
+    fn accept(listener: &TcpListener) -> TcpStream {
+        let deadline = Instant::now() + PATIENCE;
+        listener.set_nonblocking(true).unwrap();
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false).unwrap();
+                    return stream;
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    assert!(Instant::now() < deadline, "no connection arrived");
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => panic!("accept failed: {}", err),
+            }
+        }
+    }
+
+    /// Nodo falso que contesta el handshake con el id 2 y devuelve la conexión
+    fn accept_as_node_2(listener: &TcpListener) -> TcpStream {
+        let mut stream = accept(listener);
+        let addr = listener.local_addr().unwrap();
+        let tip = ChainTip::of(&Blockchain::new());
+        let theirs = Handshake::new(2, addr, WireFormat::Text, tip)
+            .exchange(&mut stream)
+            .unwrap();
+        assert_eq!(theirs.node_id(), 1);
+        stream
+    }
+
+    /// Hilo de pares de un nodo sin los demás hilos; lo que reciben queda en los canales. Al
+    /// soltarlo se cierran sus conexiones.
+    struct TestNode {
+        _peers: PeerHandler,
+        dispatcher: Dispatcher,
+        messages: Receiver<(ClientMessage, PeerIdType)>,
+        leader: Receiver<(LeaderMessage, PeerIdType)>,
+    }
+
+    fn start_node(id: PeerIdType) -> TestNode {
+        let mut config = Config::default();
+        config.set("id", &id.to_string()).unwrap();
+        config.set("reconnect-delay", "50ms").unwrap();
+        // Sin otros puertos en el rango no se llama a nadie al arrancar
+        config.set("ports", "9000-9000").unwrap();
+        let (peer_sender, peer_receiver) = channel();
+        let (message_sender, messages) = channel();
+        let (leader_sender, leader) = channel();
+        let lock = CentralizedLock::new(config.lock_expiration());
+        let lock_handler = LockProcessor::new(
+            peer_sender.clone(),
+            Arc::new((Mutex::new(lock), Condvar::new())),
+        );
+        let dispatcher = Dispatcher::new(
+            id,
+            peer_sender,
+            message_sender,
+            leader_sender,
+            channel().0,
+            lock_handler,
+        );
+        let tip = Arc::new(Mutex::new(ChainTip::of(&Blockchain::new())));
+        let listen_addr = SocketAddr::from(([127, 0, 0, id as u8], 9000));
+        let peers = PeerHandler::new(&config, listen_addr, tip, peer_receiver, dispatcher.clone());
+        TestNode {
+            _peers: peers,
+            dispatcher,
+            messages,
+            leader,
+        }
+    }
+
+    /// Espera hasta `wait` un mensaje que cumpla `expected`
+    fn received(
+        node: &TestNode,
+        wait: Duration,
+        expected: impl Fn(&ClientMessage) -> bool,
+    ) -> bool {
+        let deadline = Instant::now() + wait;
+        while let Ok((message, _)) = node
+            .messages
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if expected(&message) {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn dropped_peers_are_dialed_again_with_backoff() {
+        let node = start_node(1);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let event = ClientEvent::KnownPeers {
+            peers: vec![(2, addr)],
+        };
+        node.dispatcher.dispatch(event).unwrap();
+        drop(accept_as_node_2(&listener));
+
+        // Los intentos que el nodo 2 corta sin handshake fallan y se vuelven a programar
+        for _ in 0..2 {
+            drop(accept(&listener));
+        }
+        let _stream = accept_as_node_2(&listener);
+
+        // Cada conexión vuelve a sincronizar la cadena con el par
+        let sync = |message: &ClientMessage| matches!(message, ClientMessage::SyncStatus { .. });
+        assert!(received(&node, PATIENCE, sync));
+        assert!(received(&node, PATIENCE, sync));
+    }
+
+    /// Dos conexiones entre el par: la primera la abre el nodo 1 y la segunda el nodo 2
+    fn crossed_connections() -> [(TcpStream, TcpStream); 2] {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let pair = || {
+            let dialed = TcpStream::connect(addr).unwrap();
+            (dialed, listener.accept().unwrap().0)
+        };
+        [pair(), pair()]
+    }
+
+    /// Falso si alguno de los extremos cerró la conexión. Consume lo que haya llegado, así
+    /// que va al final de la prueba.
+    fn is_open(mut stream: &TcpStream) -> bool {
+        stream.set_nonblocking(true).unwrap();
+        let mut buffer = [0; 1024];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => return false,
+                Ok(_) => {}
+                Err(err) => return err.kind() == io::ErrorKind::WouldBlock,
+            }
+        }
+    }
+
+    /// Espera a que se cierre la conexión
+    fn closes(stream: &TcpStream) -> bool {
+        let deadline = Instant::now() + PATIENCE;
+        while is_open(stream) {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    /// Si en medio segundo el hilo de pares le avisa a la elección que se cayó un par
+    fn reports_disconnection(node: &TestNode) -> bool {
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while let Ok((message, _)) = node
+            .leader
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if matches!(message, LeaderMessage::PeerDisconnected) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Un mensaje que sale justo por la conexión que se descarta se pierde, así que se manda
+    /// de nuevo hasta que llega
+    fn delivered(from: &TestNode, to: &TestNode, to_id: PeerIdType) -> bool {
+        (0..50).any(|_| {
+            let event = ClientEvent::PeerMessage {
+                message: Message::Common(ClientMessage::SyncStatusRequest),
+                peer_id: to_id,
+            };
+            from.dispatcher.peer_sender.send(event).unwrap();
+            received(to, Duration::from_millis(200), |message| {
+                matches!(message, ClientMessage::SyncStatusRequest)
+            })
+        })
+    }
+
+    #[test]
+    fn simultaneous_connections_keep_the_one_dialed_by_the_lower_id() {
+        // Se prueban los órdenes en los que cada nodo atiende primero una u otra conexión
+        for (one_first, two_first) in [(0, 0), (1, 1), (0, 1), (1, 0)] {
+            let one = start_node(1);
+            let two = start_node(2);
+            let [(one_dials, two_accepts), (two_dials, one_accepts)] = crossed_connections();
+            let probes = [&one_dials, &two_accepts, &two_dials, &one_accepts]
+                .map(|stream| stream.try_clone().unwrap());
+            let mut one_events = vec![(one_dials, false), (one_accepts, true)];
+            let mut two_events = vec![(two_accepts, true), (two_dials, false)];
+            one_events.rotate_left(one_first);
+            two_events.rotate_left(two_first);
+            for ((one_stream, one_incoming), (two_stream, two_incoming)) in
+                one_events.into_iter().zip(two_events)
+            {
+                let event = ClientEvent::Connection {
+                    stream: one_stream,
+                    incoming: one_incoming,
+                };
+                one.dispatcher.dispatch(event).unwrap();
+                let event = ClientEvent::Connection {
+                    stream: two_stream,
+                    incoming: two_incoming,
+                };
+                two.dispatcher.dispatch(event).unwrap();
+            }
+
+            // Cada nodo registra al otro y los mensajes entre los dos pasan por la conexión
+            // que quedó
+            for node in [&one, &two] {
+                assert!(received(node, PATIENCE, |message| matches!(
+                    message,
+                    ClientMessage::SyncStatus { .. }
+                )));
+            }
+            assert!(delivered(&one, &two, 2));
+            assert!(delivered(&two, &one, 1));
+            // Cerrar la conexión repetida no es una caída para la elección de líder
+            assert!(!reports_disconnection(&one) && !reports_disconnection(&two));
+            let [one_dials, two_accepts, two_dials, one_accepts] = probes;
+            assert!(closes(&two_dials) && closes(&one_accepts));
+            assert!(is_open(&one_dials) && is_open(&two_accepts));
+        }
+    }
+}
@@ -3,28 +3,32 @@
 #[cfg(feature="color_output")]
 pub mod macros {
     macro_rules! print_fmt {
-        ($suffix:expr, $fmt:expr) => (
-            println!(concat!($suffix, "]\x1B[0m(", file!(), ":", line!(), ")\x1B[0m ", $fmt))
+        ($level:ident, $suffix:expr, $fmt:expr) => (
+            if $crate::logging::enabled($crate::logging::LogLevel::$level) {
+                println!(concat!($suffix, "]\x1B[0m(", file!(), ":", line!(), ")\x1B[0m ", $fmt))
+            }
         );
-        ($suffix:expr, $fmt:expr, $($arg:tt)*) => (
-            println!(concat!($suffix, "]\x1B[0m(", file!(), ":", line!(), ")\x1B[0m ", $fmt), $($arg)*)
+        ($level:ident, $suffix:expr, $fmt:expr, $($arg:tt)*) => (
+            if $crate::logging::enabled($crate::logging::LogLevel::$level) {
+                println!(concat!($suffix, "]\x1B[0m(", file!(), ":", line!(), ")\x1B[0m ", $fmt), $($arg)*)
+            }
         );
     }
     macro_rules! debug {
-        ($fmt:expr) => (print_fmt!("\x1B[2m[DEBUG", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("\x1B[2m[DEBUG", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Debug, "\x1B[2m[DEBUG", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Debug, "\x1B[2m[DEBUG", $fmt, $($arg)*));
     }
     macro_rules! info {
-        ($fmt:expr) => (print_fmt!("\x1B[92m[INFO ", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("\x1B[92m[INFO ", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Info, "\x1B[92m[INFO ", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Info, "\x1B[92m[INFO ", $fmt, $($arg)*));
     }
     macro_rules! warn {
-        ($fmt:expr) => (print_fmt!("\x1B[93m[WARN ", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("\x1B[93m[WARN ", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Warn, "\x1B[93m[WARN ", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Warn, "\x1B[93m[WARN ", $fmt, $($arg)*));
     }
     macro_rules! error {
-        ($fmt:expr) => (print_fmt!("\x1B[91m[ERROR", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("\x1B[91m[ERROR", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Error, "\x1B[91m[ERROR", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Error, "\x1B[91m[ERROR", $fmt, $($arg)*));
     }
 }
 
@@ -33,31 +37,36 @@ pub mod macros {
 #[cfg(not(feature="color_output"))]
 pub mod macros {
     macro_rules! print_fmt {
-        ($suffix:expr, $fmt:expr) => (
-            println!(concat!($suffix, "](", file!(), ":", line!(), ") ", $fmt))
+        ($level:ident, $suffix:expr, $fmt:expr) => (
+            if $crate::logging::enabled($crate::logging::LogLevel::$level) {
+                println!(concat!($suffix, "](", file!(), ":", line!(), ") ", $fmt))
+            }
         );
-        ($suffix:expr, $fmt:expr, $($arg:tt)*) => (
-            println!(concat!($suffix, "](", file!(), ":", line!(), ") ", $fmt), $($arg)*)
+        ($level:ident, $suffix:expr, $fmt:expr, $($arg:tt)*) => (
+            if $crate::logging::enabled($crate::logging::LogLevel::$level) {
+                println!(concat!($suffix, "](", file!(), ":", line!(), ") ", $fmt), $($arg)*)
+            }
         );
     }
     macro_rules! debug {
-        ($fmt:expr) => (print_fmt!("[DEBUG", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("[DEBUG", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Debug, "[DEBUG", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Debug, "[DEBUG", $fmt, $($arg)*));
     }
     macro_rules! info {
-        ($fmt:expr) => (print_fmt!("[INFO ", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("[INFO ", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Info, "[INFO ", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Info, "[INFO ", $fmt, $($arg)*));
     }
     macro_rules! warn {
-        ($fmt:expr) => (print_fmt!("[WARN ", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("[WARN ", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Warn, "[WARN ", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Warn, "[WARN ", $fmt, $($arg)*));
     }
     macro_rules! error {
-        ($fmt:expr) => (print_fmt!("[ERROR", $fmt));
-        ($fmt:expr, $($arg:tt)*) => (print_fmt!("[ERROR", $fmt, $($arg)*));
+        ($fmt:expr) => (print_fmt!(Error, "[ERROR", $fmt));
+        ($fmt:expr, $($arg:tt)*) => (print_fmt!(Error, "[ERROR", $fmt, $($arg)*));
     }
 }
 
 pub mod blockchain;
 pub mod communication;
 pub mod handler;
+pub mod logging;
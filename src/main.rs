@@ -1,15 +1,31 @@
 use blockchain::blockchain::client::Client;
+use blockchain::blockchain::config::{Config, ConfigError, USAGE};
+use blockchain::logging;
+use std::env;
 use std::io;
 use std::process;
 
 fn main() -> std::io::Result<()> {
-    let port_from = 9000;
-    let port_to = 9010;
-    let id = process::id();
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(ConfigError::Help) => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    logging::set_max_level(config.log_level());
     println!("################");
     println!("#  Blockchain  #");
-    println!("#  Id: {:06}  #", id);
+    println!("#  Id: {:06}  #", config.node_id());
     println!("################");
-    let mut client = Client::new(id);
-    client.run(io::stdin(), port_from, port_to)
+    if let Some(dir) = config.data_dir() {
+        println!("Data dir: {}", dir.display());
+    }
+    println!("Wire format: {}", config.wire_format().name());
+    let mut client = Client::new(config);
+    client.run(io::stdin())
 }
@@ -22,7 +22,7 @@ echo "rb"
 
 {
 sleep 2
-echo "wb insert p 2"
+echo "wb insert p algo1 parcial 2"
 } > input_b.fifo &
 
 launch_process a 0